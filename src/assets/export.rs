@@ -0,0 +1,150 @@
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use crate::language::types::{Model, TextureVertex};
+use crate::tvk_glm::{normal_mat3, transform_normal, transform_point};
+
+/// Vertices of `model` with its transform applied, as they appear on screen
+/// (instancing aside).
+fn baked_vertices(model: &Model) -> Vec<TextureVertex> {
+    let matrix = model.world_mat4();
+    let normal_matrix = normal_mat3(matrix);
+    model
+        .vertices
+        .iter()
+        .map(|v| TextureVertex {
+            position: transform_point(matrix, v.position),
+            uv: v.uv,
+            normal: transform_normal(normal_matrix, v.normal),
+        })
+        .collect()
+}
+
+fn check_topology(model: &Model) -> Result<(), String> {
+    if model.topology != "RESERVED_TRIANGLE_LIST" {
+        return Err(format!(
+            "{}: only triangle lists can be exported",
+            model.name
+        ));
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: String) -> Result<(), String> {
+    std::fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Obj,
+    Ply,
+}
+
+/// Export asked for by a script. Scripts are evaluated on every edit, so they
+/// are queued and only written when the script is run on purpose.
+pub struct Export {
+    pub format: ExportFormat,
+    pub models: Vec<Model>,
+    pub path: PathBuf,
+}
+
+impl Export {
+    pub fn write(&self) -> Result<(), String> {
+        match self.format {
+            ExportFormat::Obj => export_obj(&self.models, &self.path),
+            ExportFormat::Ply => export_ply(&self.models, &self.path),
+        }
+    }
+}
+
+/// Turns a model label, which may come from OBJ groups or glTF nodes, into a
+/// file name without directories: anything but letters, digits, `-` and `_`
+/// becomes `_`.
+pub fn file_stem(label: &str) -> String {
+    let stem: String = label
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if stem.is_empty() {
+        "model".to_string()
+    } else {
+        stem
+    }
+}
+
+/// Writes every model as its own object of a Wavefront OBJ file.
+pub fn export_obj(models: &[Model], path: &Path) -> Result<(), String> {
+    let mut out = String::new();
+    let mut offset = 1;
+    for model in models {
+        check_topology(model)?;
+        let vertices = baked_vertices(model);
+        let has_normals = vertices.iter().any(|v| v.normal != [0.0, 0.0, 0.0]);
+        writeln!(out, "o {}", model.name).unwrap();
+        for v in &vertices {
+            let [x, y, z] = v.position;
+            writeln!(out, "v {} {} {}", x, y, z).unwrap();
+        }
+        for v in &vertices {
+            // Back to OBJ's bottom left texture origin.
+            writeln!(out, "vt {} {}", v.uv[0], 1.0 - v.uv[1]).unwrap();
+        }
+        if has_normals {
+            for v in &vertices {
+                let [x, y, z] = v.normal;
+                writeln!(out, "vn {} {} {}", x, y, z).unwrap();
+            }
+        }
+        for face in model.indices.chunks_exact(3) {
+            out.push('f');
+            for i in face {
+                let i = i + offset;
+                if has_normals {
+                    write!(out, " {}/{}/{}", i, i, i).unwrap();
+                } else {
+                    write!(out, " {}/{}", i, i).unwrap();
+                }
+            }
+            out.push('\n');
+        }
+        offset += vertices.len() as u32;
+    }
+    write_file(path, out)
+}
+
+/// Writes all models merged into a single ASCII PLY mesh.
+pub fn export_ply(models: &[Model], path: &Path) -> Result<(), String> {
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+    for model in models {
+        check_topology(model)?;
+        let offset = vertices.len() as u32;
+        faces.extend(
+            model
+                .indices
+                .chunks_exact(3)
+                .map(|f| [f[0] + offset, f[1] + offset, f[2] + offset]),
+        );
+        vertices.extend(baked_vertices(model));
+    }
+
+    let mut out = String::new();
+    out.push_str("ply\nformat ascii 1.0\n");
+    writeln!(out, "element vertex {}", vertices.len()).unwrap();
+    for property in ["x", "y", "z", "nx", "ny", "nz", "s", "t"] {
+        writeln!(out, "property float {}", property).unwrap();
+    }
+    writeln!(out, "element face {}", faces.len()).unwrap();
+    out.push_str("property list uchar uint vertex_indices\nend_header\n");
+    for v in &vertices {
+        let [x, y, z] = v.position;
+        let [nx, ny, nz] = v.normal;
+        writeln!(out, "{} {} {} {} {} {} {} {}", x, y, z, nx, ny, nz, v.uv[0], 1.0 - v.uv[1]).unwrap();
+    }
+    for [a, b, c] in faces {
+        writeln!(out, "3 {} {} {}", a, b, c).unwrap();
+    }
+    write_file(path, out)
+}
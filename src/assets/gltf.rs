@@ -0,0 +1,163 @@
+use std::path::Path;
+
+use gltf::image::Format;
+
+use crate::language::types::TextureVertex;
+use crate::tvk_glm::{identity_mat4, mult_mat4, normal_mat3, transform_normal, transform_point};
+
+/// Decoded image referenced by a glTF material. `index` identifies it inside
+/// the file so equal images are only uploaded once.
+#[derive(Clone, Debug)]
+pub struct GltfImage {
+    pub index: usize,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GltfMaterial {
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<GltfImage>,
+    pub normal_texture: Option<GltfImage>,
+    pub blend: bool,
+}
+
+/// One mesh primitive with its node's world transform baked into the vertices.
+#[derive(Clone, Debug)]
+pub struct GltfPrimitive {
+    pub name: String,
+    pub vertices: Vec<TextureVertex>,
+    pub indices: Vec<u32>,
+    pub material: GltfMaterial,
+}
+
+pub fn load_gltf(path: &Path) -> Result<Vec<GltfPrimitive>, String> {
+    let (document, buffers, images) =
+        gltf::import(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| format!("{}: no scenes", path.display()))?;
+    let mut primitives = Vec::new();
+    for node in scene.nodes() {
+        visit_node(&node, identity_mat4(), &buffers, &images, &mut primitives)?;
+    }
+    Ok(primitives)
+}
+
+fn visit_node(
+    node: &gltf::Node,
+    parent: [[f32; 4]; 4],
+    buffers: &[gltf::buffer::Data],
+    images: &[gltf::image::Data],
+    primitives: &mut Vec<GltfPrimitive>,
+) -> Result<(), String> {
+    // mult_mat4(a, b) computes b * a for our column-major matrices.
+    let world = mult_mat4(node.transform().matrix(), parent);
+    if let Some(mesh) = node.mesh() {
+        let name = node
+            .name()
+            .or_else(|| mesh.name())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("node{}", node.index()));
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                Some(positions) => positions.collect(),
+                None => continue,
+            };
+            let normals: Vec<[f32; 3]> = reader
+                .read_normals()
+                .map(|n| n.collect())
+                .unwrap_or_default();
+            let uvs: Vec<[f32; 2]> = reader
+                .read_tex_coords(0)
+                .map(|uv| uv.into_f32().collect())
+                .unwrap_or_default();
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            let normal_matrix = normal_mat3(world);
+            let vertices = positions
+                .iter()
+                .enumerate()
+                .map(|(i, p)| TextureVertex {
+                    position: transform_point(world, *p),
+                    uv: uvs.get(i).copied().unwrap_or([0.0, 0.0]),
+                    normal: normals
+                        .get(i)
+                        .map(|n| transform_normal(normal_matrix, *n))
+                        .unwrap_or([0.0, 0.0, 0.0]),
+                })
+                .collect();
+            primitives.push(GltfPrimitive {
+                name: format!("{}.{}", name, primitive.index()),
+                vertices,
+                indices,
+                material: read_material(&primitive.material(), images)?,
+            });
+        }
+    }
+    for child in node.children() {
+        visit_node(&child, world, buffers, images, primitives)?;
+    }
+    Ok(())
+}
+
+fn read_material(
+    material: &gltf::Material,
+    images: &[gltf::image::Data],
+) -> Result<GltfMaterial, String> {
+    let pbr = material.pbr_metallic_roughness();
+    let base_color_texture = match pbr.base_color_texture() {
+        Some(info) => Some(read_image(info.texture().source().index(), images)?),
+        None => None,
+    };
+    let normal_texture = match material.normal_texture() {
+        Some(normal) => Some(read_image(normal.texture().source().index(), images)?),
+        None => None,
+    };
+    Ok(GltfMaterial {
+        base_color: pbr.base_color_factor(),
+        base_color_texture,
+        normal_texture,
+        blend: material.alpha_mode() == gltf::material::AlphaMode::Blend,
+    })
+}
+
+/// Expands any 8 or 16 bit glTF image to RGBA8. 16 bit channels keep their
+/// most significant byte.
+fn read_image(index: usize, images: &[gltf::image::Data]) -> Result<GltfImage, String> {
+    let image = images
+        .get(index)
+        .ok_or_else(|| format!("missing image {}", index))?;
+    let p = &image.pixels;
+    let data: Vec<u8> = match image.format {
+        Format::R8 => p.iter().flat_map(|&r| [r, r, r, 255]).collect(),
+        Format::R8G8 => p.chunks_exact(2).flat_map(|c| [c[0], c[1], 0, 255]).collect(),
+        Format::R8G8B8 => p.chunks_exact(3).flat_map(|c| [c[0], c[1], c[2], 255]).collect(),
+        Format::R8G8B8A8 => p.clone(),
+        Format::R16 => p.chunks_exact(2).flat_map(|c| [c[1], c[1], c[1], 255]).collect(),
+        Format::R16G16 => p.chunks_exact(4).flat_map(|c| [c[1], c[3], 0, 255]).collect(),
+        Format::R16G16B16 => p
+            .chunks_exact(6)
+            .flat_map(|c| [c[1], c[3], c[5], 255])
+            .collect(),
+        Format::R16G16B16A16 => p
+            .chunks_exact(8)
+            .flat_map(|c| [c[1], c[3], c[5], c[7]])
+            .collect(),
+        format => return Err(format!("image {}: unsupported format {:?}", index, format)),
+    };
+    Ok(GltfImage {
+        index,
+        width: image.width,
+        height: image.height,
+        data,
+    })
+}
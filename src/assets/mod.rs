@@ -0,0 +1,4 @@
+pub mod export;
+pub mod gltf;
+pub mod obj;
+pub mod shapes;
@@ -0,0 +1,309 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::language::types::TextureVertex;
+
+#[derive(Clone, Debug)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub diffuse: [f32; 3],
+    pub dissolve: f32,
+    pub shininess: f32,
+    pub diffuse_map: Option<PathBuf>,
+    pub normal_map: Option<PathBuf>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: [1.0, 1.0, 1.0],
+            dissolve: 1.0,
+            shininess: 32.0,
+            diffuse_map: None,
+            normal_map: None,
+        }
+    }
+}
+
+/// Triangles sharing a group name and material. Vertices are local to the
+/// group so every group can become a model on its own.
+#[derive(Clone, Debug)]
+pub struct ObjGroup {
+    pub name: String,
+    pub vertices: Vec<TextureVertex>,
+    pub indices: Vec<u32>,
+    pub material: Option<ObjMaterial>,
+}
+
+pub struct LoadedObj {
+    pub groups: Vec<ObjGroup>,
+    /// Problems that didn't stop the geometry from loading, like a missing
+    /// material library.
+    pub warnings: Vec<String>,
+}
+
+struct GroupBuilder {
+    group: ObjGroup,
+    lookup: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+}
+
+fn parse_floats(tokens: &[&str], line: usize) -> Result<Vec<f32>, String> {
+    tokens
+        .iter()
+        .map(|t| t.parse::<f32>().map_err(|_| format!("line {}: bad number {}", line, t)))
+        .collect()
+}
+
+/// OBJ indices are 1-based and may be negative, counting back from the last
+/// element defined so far.
+fn resolve_index(token: &str, len: usize, line: usize) -> Result<usize, String> {
+    let i: i64 = token
+        .parse()
+        .map_err(|_| format!("line {}: bad index {}", line, token))?;
+    let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!("line {}: index {} out of range", line, token));
+    }
+    Ok(resolved as usize)
+}
+
+pub fn load_obj(path: &Path) -> Result<LoadedObj, String> {
+    let source =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    parse_obj(&source, dir, |mtl| std::fs::read_to_string(mtl))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Parses an OBJ file, reading the material libraries it mentions with
+/// `read_mtl`. Groups whose library can't be read keep no material.
+fn parse_obj(
+    source: &str,
+    dir: &Path,
+    read_mtl: impl Fn(&Path) -> std::io::Result<String>,
+) -> Result<LoadedObj, String> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut materials: HashMap<String, ObjMaterial> = HashMap::new();
+    let mut groups: Vec<GroupBuilder> = Vec::new();
+    let mut group_name = "default".to_string();
+    let mut material: Option<String> = None;
+    let mut current: Option<usize> = None;
+    let mut warnings = Vec::new();
+
+    for (n, line) in source.lines().enumerate() {
+        let n = n + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, args) = match tokens.split_first() {
+            Some((keyword, args)) => (*keyword, args),
+            None => continue,
+        };
+        match keyword {
+            "v" => {
+                let v = parse_floats(args, n)?;
+                if v.len() < 3 {
+                    return Err(format!("line {}: vertex needs 3 coordinates", n));
+                }
+                positions.push([v[0], v[1], v[2]]);
+            }
+            "vt" => {
+                let v = parse_floats(args, n)?;
+                let u = v.first().copied().unwrap_or(0.0);
+                let v = v.get(1).copied().unwrap_or(0.0);
+                // OBJ puts the texture origin at the bottom left, Vulkan at the top left.
+                uvs.push([u, 1.0 - v]);
+            }
+            "vn" => {
+                let v = parse_floats(args, n)?;
+                if v.len() < 3 {
+                    return Err(format!("line {}: normal needs 3 coordinates", n));
+                }
+                normals.push([v[0], v[1], v[2]]);
+            }
+            "g" | "o" => {
+                group_name = args.join(" ");
+                current = None;
+            }
+            "usemtl" => {
+                material = args.first().map(|s| s.to_string());
+                current = None;
+            }
+            "mtllib" => {
+                for lib in args {
+                    let mtl_path = dir.join(lib);
+                    let mtl = match read_mtl(&mtl_path) {
+                        Ok(mtl) => mtl,
+                        Err(e) => {
+                            warnings.push(format!("{}: {}, using the default material", mtl_path.display(), e));
+                            continue;
+                        }
+                    };
+                    for m in parse_mtl(&mtl, dir) {
+                        materials.insert(m.name.clone(), m);
+                    }
+                }
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(format!("line {}: face needs at least 3 vertices", n));
+                }
+                let index = match current {
+                    Some(index) => index,
+                    None => {
+                        groups.push(GroupBuilder {
+                            group: ObjGroup {
+                                name: group_name.clone(),
+                                vertices: Vec::new(),
+                                indices: Vec::new(),
+                                material: material.as_ref().and_then(|m| materials.get(m)).cloned(),
+                            },
+                            lookup: HashMap::new(),
+                        });
+                        current = Some(groups.len() - 1);
+                        groups.len() - 1
+                    }
+                };
+                let builder = &mut groups[index];
+                let mut face = Vec::with_capacity(args.len());
+                for corner in args {
+                    let mut parts = corner.split('/');
+                    let v = resolve_index(parts.next().unwrap_or(""), positions.len(), n)?;
+                    let vt = match parts.next() {
+                        Some(t) if !t.is_empty() => Some(resolve_index(t, uvs.len(), n)?),
+                        _ => None,
+                    };
+                    let vn = match parts.next() {
+                        Some(t) if !t.is_empty() => Some(resolve_index(t, normals.len(), n)?),
+                        _ => None,
+                    };
+                    let vertices = &mut builder.group.vertices;
+                    let i = *builder.lookup.entry((v, vt, vn)).or_insert_with(|| {
+                        vertices.push(TextureVertex {
+                            position: positions[v],
+                            uv: vt.map(|i| uvs[i]).unwrap_or([0.0, 0.0]),
+                            normal: vn.map(|i| normals[i]).unwrap_or([0.0, 0.0, 0.0]),
+                        });
+                        vertices.len() as u32 - 1
+                    });
+                    face.push(i);
+                }
+                // Polygons are split as a fan around their first vertex.
+                for k in 1..face.len() - 1 {
+                    builder.group.indices.extend([face[0], face[k], face[k + 1]]);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let groups = groups
+        .into_iter()
+        .map(|b| b.group)
+        .filter(|g| !g.indices.is_empty())
+        .collect();
+    Ok(LoadedObj { groups, warnings })
+}
+
+fn parse_mtl(source: &str, dir: &Path) -> Vec<ObjMaterial> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+    for line in source.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, args) = match tokens.split_first() {
+            Some((keyword, args)) => (*keyword, args),
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            materials.push(ObjMaterial::new(&args.join(" ")));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => continue,
+        };
+        let floats: Vec<f32> = args.iter().filter_map(|t| t.parse().ok()).collect();
+        match keyword {
+            "Kd" if floats.len() >= 3 => material.diffuse = [floats[0], floats[1], floats[2]],
+            "Ns" if !floats.is_empty() => material.shininess = floats[0],
+            "d" if !floats.is_empty() => material.dissolve = floats[0],
+            "Tr" if !floats.is_empty() => material.dissolve = 1.0 - floats[0],
+            // Map statements may carry options (-s, -bm...) before the file name.
+            "map_Kd" => material.diffuse_map = args.last().map(|f| dir.join(f)),
+            "map_Bump" | "bump" | "norm" => material.normal_map = args.last().map(|f| dir.join(f)),
+            _ => (),
+        }
+    }
+    materials
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> LoadedObj {
+        parse_obj(source, Path::new("models"), |path| {
+            match path.to_str() {
+                Some("models/red.mtl") => Ok("newmtl red\nKd 1 0 0\nNs 10\nmap_Kd -s 2 2 2 red.png\n".to_string()),
+                _ => Err(std::io::Error::new(std::io::ErrorKind::NotFound, "not found")),
+            }
+        })
+        .unwrap()
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn negative_indices_count_back() {
+        let obj = parse(&format!("{}f -4 -3 -2\n", SQUARE));
+        let positions: Vec<_> = obj.groups[0].vertices.iter().map(|v| v.position).collect();
+        assert_eq!(positions, [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]]);
+        assert_eq!(obj.groups[0].indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn reads_every_corner_form() {
+        let source = format!(
+            "{}vt 0 0\nvt 1 0.25\nvn 0 0 1\nf 1 2 3\nf 1/1 2/2 3/1\nf 1//1 2//1 3//1\nf 1/2/1 2/1/1 3/2/1\n",
+            SQUARE
+        );
+        let group = &parse(&source).groups[0];
+        assert_eq!(group.indices.len(), 12);
+        // `1`, `1/1`, `1//1` and `1/2/1` are four different vertices.
+        assert_eq!(group.vertices.len(), 12);
+        let with_uv = group.vertices.iter().find(|v| v.uv == [1.0, 0.75]).unwrap();
+        assert_eq!(with_uv.position, [1.0, 0.0, 0.0]);
+        assert!(group.vertices.iter().any(|v| v.normal == [0.0, 0.0, 1.0]));
+        assert!(parse_obj("v 0 0 0\nf 1 1 2\n", Path::new("."), |_| Ok(String::new())).is_err());
+    }
+
+    #[test]
+    fn polygons_become_a_triangle_fan() {
+        let obj = parse(&format!("{}f 1 2 3 4\n", SQUARE));
+        assert_eq!(obj.groups[0].indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn usemtl_may_come_before_mtllib() {
+        let obj = parse(&format!("usemtl red\nmtllib red.mtl\n{}f 1 2 3\n", SQUARE));
+        let material = obj.groups[0].material.as_ref().unwrap();
+        assert_eq!(material.name, "red");
+        assert_eq!(material.diffuse, [1.0, 0.0, 0.0]);
+        assert_eq!(material.shininess, 10.0);
+        assert_eq!(material.diffuse_map, Some(PathBuf::from("models/red.png")));
+        assert!(obj.warnings.is_empty());
+    }
+
+    #[test]
+    fn missing_material_libraries_only_warn() {
+        let obj = parse(&format!("mtllib gone.mtl\nusemtl red\n{}f 1 2 3\n", SQUARE));
+        assert_eq!(obj.groups.len(), 1);
+        assert!(obj.groups[0].material.is_none());
+        assert_eq!(obj.warnings.len(), 1);
+        assert!(obj.warnings[0].starts_with("models/gone.mtl"));
+    }
+}
@@ -0,0 +1,221 @@
+use std::f32::consts::PI;
+
+use crate::language::types::{MeshGroup, MeshId, TextureVertex};
+use crate::tvk_glm::{cross_vec3, normalize_vec3, vec3_subs, vector_scalar_mult3};
+
+// Every generator winds its triangles counter-clockwise seen from outside and
+// puts the texture origin at the top left, like Vulkan samples it.
+
+fn vertex(position: [f32; 3], uv: [f32; 2], normal: [f32; 3]) -> TextureVertex {
+    TextureVertex {
+        position,
+        uv,
+        normal,
+    }
+}
+
+fn group(name: &str, vertices: Vec<TextureVertex>, indices: Vec<u32>) -> MeshGroup {
+    MeshGroup {
+        name: name.to_string(),
+        vertices,
+        indices,
+        material: None,
+        id: MeshId::new(),
+    }
+}
+
+/// Indices for a `(rows + 1) x (columns + 1)` vertex grid stored row by row,
+/// with rows going down the surface and columns going around it.
+fn grid_indices(rows: u32, columns: u32) -> Vec<u32> {
+    let mut indices = Vec::with_capacity((rows * columns * 6) as usize);
+    for i in 0..rows {
+        for j in 0..columns {
+            let a = i * (columns + 1) + j;
+            let b = a + columns + 1;
+            indices.extend([a, b, b + 1, a, b + 1, a + 1]);
+        }
+    }
+    indices
+}
+
+/// Axis aligned cube centered on the origin, each face with its own vertices
+/// so normals and UVs don't get shared across edges.
+pub fn cube(size: f32) -> MeshGroup {
+    let h = size / 2.0;
+    // (normal, u axis, v axis) with u x v = normal.
+    let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ];
+    let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for (normal, u, v) in faces {
+        let start = vertices.len() as u32;
+        for (su, sv) in corners {
+            let position = [0, 1, 2].map(|k| (normal[k] + u[k] * su + v[k] * sv) * h);
+            vertices.push(vertex(position, [(su + 1.0) / 2.0, (1.0 - sv) / 2.0], normal));
+        }
+        indices.extend([start, start + 1, start + 2, start, start + 2, start + 3]);
+    }
+    group("cube", vertices, indices)
+}
+
+/// UV sphere with `rings` bands from pole to pole and `segments` around the
+/// Y axis. The seam vertices are duplicated so the texture wraps once.
+pub fn sphere(radius: f32, rings: u32, segments: u32) -> MeshGroup {
+    let rings = rings.max(2);
+    let segments = segments.max(3);
+    let mut vertices = Vec::with_capacity(((rings + 1) * (segments + 1)) as usize);
+    for i in 0..=rings {
+        let phi = PI * i as f32 / rings as f32;
+        for j in 0..=segments {
+            let theta = 2.0 * PI * j as f32 / segments as f32;
+            let normal = [phi.sin() * theta.sin(), phi.cos(), phi.sin() * theta.cos()];
+            vertices.push(vertex(
+                vector_scalar_mult3(normal, radius),
+                [j as f32 / segments as f32, i as f32 / rings as f32],
+                normal,
+            ));
+        }
+    }
+    group("sphere", vertices, grid_indices(rings, segments))
+}
+
+/// Side and caps of a truncated cone along Y, centered on the origin. Caps
+/// with a zero radius are left out.
+fn frustum(name: &str, bottom: f32, top: f32, height: f32, segments: u32) -> MeshGroup {
+    let segments = segments.max(3);
+    let h = height / 2.0;
+    let mut vertices = Vec::new();
+    for (y, radius, v) in [(h, top, 0.0), (-h, bottom, 1.0)] {
+        for j in 0..=segments {
+            let theta = 2.0 * PI * j as f32 / segments as f32;
+            let (s, c) = theta.sin_cos();
+            let normal = normalize_vec3([s * height, bottom - top, c * height]);
+            vertices.push(vertex(
+                [s * radius, y, c * radius],
+                [j as f32 / segments as f32, v],
+                normal,
+            ));
+        }
+    }
+    let mut indices = grid_indices(1, segments);
+    for (y, radius, up) in [(h, top, 1.0), (-h, bottom, -1.0)] {
+        if radius <= 0.0 {
+            continue;
+        }
+        let normal = [0.0, up, 0.0];
+        let center = vertices.len() as u32;
+        vertices.push(vertex([0.0, y, 0.0], [0.5, 0.5], normal));
+        for j in 0..=segments {
+            let theta = 2.0 * PI * j as f32 / segments as f32;
+            let (s, c) = theta.sin_cos();
+            vertices.push(vertex(
+                [s * radius, y, c * radius],
+                [0.5 + 0.5 * s, 0.5 - 0.5 * c * up],
+                normal,
+            ));
+        }
+        for j in 0..segments {
+            let (a, b) = (center + 1 + j, center + 2 + j);
+            if up > 0.0 {
+                indices.extend([center, a, b]);
+            } else {
+                indices.extend([center, b, a]);
+            }
+        }
+    }
+    group(name, vertices, indices)
+}
+
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshGroup {
+    frustum("cylinder", radius, radius, height, segments)
+}
+
+pub fn cone(radius: f32, height: f32, segments: u32) -> MeshGroup {
+    frustum("cone", radius, 0.0, height, segments)
+}
+
+/// Torus lying on the XZ plane. `rings` split the main circle and `segments`
+/// the tube around it.
+pub fn torus(major: f32, minor: f32, rings: u32, segments: u32) -> MeshGroup {
+    let rings = rings.max(3);
+    let segments = segments.max(3);
+    let mut vertices = Vec::with_capacity(((rings + 1) * (segments + 1)) as usize);
+    for i in 0..=rings {
+        let theta = 2.0 * PI * i as f32 / rings as f32;
+        let (s, c) = theta.sin_cos();
+        let out = [s, 0.0, c];
+        for j in 0..=segments {
+            let phi = 2.0 * PI * j as f32 / segments as f32;
+            let normal = [out[0] * phi.cos(), phi.sin(), out[2] * phi.cos()];
+            let position = [0, 1, 2].map(|k| out[k] * major + normal[k] * minor);
+            vertices.push(vertex(
+                position,
+                [i as f32 / rings as f32, j as f32 / segments as f32],
+                normal,
+            ));
+        }
+    }
+    group("torus", vertices, grid_indices(rings, segments))
+}
+
+/// Plane on XZ facing +Y, split in `subdivisions` squares along each side.
+pub fn plane(width: f32, depth: f32, subdivisions: u32) -> MeshGroup {
+    let n = subdivisions.max(1);
+    let mut vertices = Vec::with_capacity(((n + 1) * (n + 1)) as usize);
+    for i in 0..=n {
+        let z = -depth / 2.0 + depth * i as f32 / n as f32;
+        for j in 0..=n {
+            let x = -width / 2.0 + width * j as f32 / n as f32;
+            vertices.push(vertex(
+                [x, 0.0, z],
+                [j as f32 / n as f32, i as f32 / n as f32],
+                [0.0, 1.0, 0.0],
+            ));
+        }
+    }
+    group("plane", vertices, grid_indices(n, n))
+}
+
+/// Triangulates `(rows + 1) x (columns + 1)` points sampled row by row from a
+/// parametric surface. Normals come from central differences between the
+/// neighbouring samples.
+pub fn surface(points: &[[f32; 3]], rows: u32, columns: u32) -> MeshGroup {
+    let at = |i: u32, j: u32| points[(i * (columns + 1) + j) as usize];
+    let mut vertices = Vec::with_capacity(points.len());
+    for i in 0..=rows {
+        for j in 0..=columns {
+            let du = vec3_subs(at(i, (j + 1).min(columns)), at(i, j.saturating_sub(1)));
+            let dv = vec3_subs(at((i + 1).min(rows), j), at(i.saturating_sub(1), j));
+            let normal = cross_vec3(dv, du);
+            let normal = if normal == [0.0, 0.0, 0.0] {
+                normal
+            } else {
+                normalize_vec3(normal)
+            };
+            vertices.push(vertex(
+                at(i, j),
+                [j as f32 / columns as f32, i as f32 / rows as f32],
+                normal,
+            ));
+        }
+    }
+    group("surface", vertices, grid_indices(rows, columns))
+}
+
+/// Line strip through `points`, meant to be drawn with a line-strip topology.
+pub fn polyline(name: &str, points: &[[f32; 3]]) -> MeshGroup {
+    let last = points.len().saturating_sub(1).max(1) as f32;
+    let vertices = points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| vertex(*p, [i as f32 / last, 0.0], [0.0, 0.0, 0.0]))
+        .collect();
+    group(name, vertices, (0..points.len() as u32).collect())
+}
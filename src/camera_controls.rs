@@ -0,0 +1,389 @@
+use std::collections::HashSet;
+
+use winit::event::{MouseButton, MouseScrollDelta, VirtualKeyCode};
+
+use crate::language::parser::Parser;
+use crate::language::types::{Camera, Projection, TvkObject};
+use crate::tvk_glm::{quaternion::Quat, vector::Vec3};
+
+/// Radians turned per dragged pixel.
+const ROTATE_SPEED: f32 = 0.005;
+/// Fraction of the distance to `center` moved per dragged pixel.
+const PAN_SPEED: f32 = 0.002;
+/// Fraction of the distance to `center` moved per second of flying.
+const FLY_SPEED: f32 = 1.0;
+/// Scale of the distance to `center` per scrolled line.
+const ZOOM_STEP: f32 = 0.9;
+/// Pixels a button can move between press and release and still be a click.
+const CLICK_SLOP: f32 = 3.0;
+
+/// Mouse and keyboard navigation of the viewport camera. Dragging with the
+/// left button orbits around `center`, with the middle one (or shift and the
+/// left one) pans and with the right one looks around. The wheel zooms, and
+/// W, A, S, D, Q and E fly forward, left, back, right, down and up.
+#[derive(Default)]
+pub struct CameraControls {
+    drag: Option<MouseButton>,
+    dragged: f32,
+    cursor: [f32; 2],
+    shift: bool,
+    keys: HashSet<VirtualKeyCode>,
+}
+
+impl CameraControls {
+    pub fn press(&mut self, button: MouseButton) {
+        if self.drag.is_none() {
+            self.drag = Some(button);
+            self.dragged = 0.0;
+        }
+    }
+
+    /// Ends the drag started by `button`. Returns true when the cursor barely
+    /// moved, in which case it was a click.
+    pub fn release(&mut self, button: MouseButton) -> bool {
+        if self.drag != Some(button) {
+            return false;
+        }
+        self.drag = None;
+        self.dragged < CLICK_SLOP
+    }
+
+    pub fn set_shift(&mut self, shift: bool) {
+        self.shift = shift;
+    }
+
+    pub fn key(&mut self, key: VirtualKeyCode, pressed: bool) {
+        if pressed {
+            self.keys.insert(key);
+        } else {
+            self.keys.remove(&key);
+        }
+    }
+
+    /// Forgets held keys and buttons, their releases are lost when the window
+    /// loses focus.
+    pub fn release_all(&mut self) {
+        self.drag = None;
+        self.keys.clear();
+    }
+
+    /// Returns whether `camera` moved.
+    pub fn cursor_moved(&mut self, cursor: [f32; 2], camera: &mut Camera) -> bool {
+        let delta = [cursor[0] - self.cursor[0], cursor[1] - self.cursor[1]];
+        self.cursor = cursor;
+        let button = match self.drag {
+            Some(button) => button,
+            None => return false,
+        };
+        self.dragged += delta[0].abs() + delta[1].abs();
+        match button {
+            MouseButton::Left if !self.shift => orbit(camera, delta),
+            MouseButton::Left | MouseButton::Middle => pan(camera, delta),
+            MouseButton::Right => look(camera, delta),
+            MouseButton::Other(_) => return false,
+        }
+        true
+    }
+
+    pub fn scroll(&mut self, delta: MouseScrollDelta, camera: &mut Camera) -> bool {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+        };
+        zoom(camera, ZOOM_STEP.powf(lines));
+        lines != 0.0
+    }
+
+    /// Flies `camera` for `dt` seconds with the held keys. Returns whether it
+    /// moved.
+    pub fn update(&mut self, dt: f32, camera: &mut Camera) -> bool {
+        let frame = Frame::new(camera);
+        let direction = self.keys.iter().fold(Vec3::ZERO, |direction, key| {
+            direction
+                + match key {
+                    VirtualKeyCode::W => frame.forward,
+                    VirtualKeyCode::S => -frame.forward,
+                    VirtualKeyCode::D => frame.right,
+                    VirtualKeyCode::A => -frame.right,
+                    VirtualKeyCode::E => frame.up,
+                    VirtualKeyCode::Q => -frame.up,
+                    _ => Vec3::ZERO,
+                }
+        });
+        if direction == Vec3::ZERO {
+            return false;
+        }
+        translate(camera, direction * frame.distance * FLY_SPEED * dt);
+        true
+    }
+}
+
+/// Directions the camera sees as forward, right and up.
+struct Frame {
+    position: Vec3,
+    center: Vec3,
+    forward: Vec3,
+    right: Vec3,
+    up: Vec3,
+    distance: f32,
+}
+
+impl Frame {
+    fn new(camera: &Camera) -> Self {
+        let position = Vec3::from(camera.position);
+        let center = Vec3::from(camera.center);
+        let forward = (center - position).normalize();
+        let right = forward.cross(camera.up.into()).normalize();
+        Self {
+            position,
+            center,
+            forward,
+            right,
+            up: right.cross(forward),
+            distance: position.distance(center),
+        }
+    }
+}
+
+fn orbit(camera: &mut Camera, delta: [f32; 2]) {
+    let frame = Frame::new(camera);
+    let up = Vec3::from(camera.up).normalize();
+    let offset = frame.position - frame.center;
+    let pitched = Quat::from_axis_angle(frame.right, -delta[1] * ROTATE_SPEED) * offset;
+    // Stop at the poles instead of flipping over them.
+    let offset = if pitched.normalize().dot(up).abs() < 0.999 {
+        pitched
+    } else {
+        offset
+    };
+    let yaw = Quat::from_axis_angle(up, -delta[0] * ROTATE_SPEED);
+    camera.position = (frame.center + yaw * offset).into();
+}
+
+fn pan(camera: &mut Camera, delta: [f32; 2]) {
+    let frame = Frame::new(camera);
+    let step = (frame.up * delta[1] - frame.right * delta[0]) * frame.distance * PAN_SPEED;
+    translate(camera, step);
+}
+
+fn look(camera: &mut Camera, delta: [f32; 2]) {
+    let frame = Frame::new(camera);
+    let up = Vec3::from(camera.up).normalize();
+    let view = frame.center - frame.position;
+    let pitched = Quat::from_axis_angle(frame.right, -delta[1] * ROTATE_SPEED) * view;
+    let view = if pitched.normalize().dot(up).abs() < 0.999 {
+        pitched
+    } else {
+        view
+    };
+    let yaw = Quat::from_axis_angle(up, -delta[0] * ROTATE_SPEED);
+    camera.center = (frame.position + yaw * view).into();
+}
+
+/// Orthographic cameras zoom by shrinking their box, moving them wouldn't
+/// change what they see.
+fn zoom(camera: &mut Camera, factor: f32) {
+    match &mut camera.projection {
+        Projection::Perspective { .. } => {
+            let frame = Frame::new(camera);
+            let offset = (frame.position - frame.center) * factor;
+            if offset.length() > 1e-3 {
+                camera.position = (frame.center + offset).into();
+            }
+        }
+        Projection::Orthographic {
+            left,
+            right,
+            bottom,
+            top,
+            ..
+        } => {
+            for bound in [left, right, bottom, top] {
+                *bound *= factor;
+            }
+        }
+    }
+}
+
+fn translate(camera: &mut Camera, step: Vec3) {
+    camera.position = (Vec3::from(camera.position) + step).into();
+    camera.center = (Vec3::from(camera.center) + step).into();
+}
+
+/// Name of the binding written by `copy_to_script`.
+const SCRIPT_CAMERA: &str = "view-camera";
+
+/// Writes `camera` into `code` as `(def view-camera (camera ...))`, replacing
+/// the top level form written by a previous copy if there is one. Cameras
+/// with NaN or infinite values are refused, the script couldn't read them.
+pub fn copy_to_script(code: &mut String, camera: &Camera) -> Result<(), String> {
+    if !is_finite(camera) {
+        return Err("copy camera: the camera has non-finite values".to_string());
+    }
+    let form = camera_form(camera);
+    let mut parser = Parser::new(code);
+    let exprs = parser.parse();
+    let previous = exprs.iter().position(|expr| match expr {
+        TvkObject::List(l) => matches!(
+            l.as_slice(),
+            [TvkObject::Atom("def"), TvkObject::Atom(SCRIPT_CAMERA), ..]
+        ),
+        _ => false,
+    });
+    match previous {
+        Some(i) => {
+            let range = parser.starts[i]..parser.ends[i];
+            code.replace_range(range, &form);
+        }
+        None => {
+            if !code.is_empty() && !code.ends_with('\n') {
+                code.push('\n');
+            }
+            code.push_str(&form);
+        }
+    }
+    Ok(())
+}
+
+fn is_finite(camera: &Camera) -> bool {
+    let projection = match camera.projection {
+        Projection::Perspective {
+            fovy,
+            z_near,
+            z_far,
+        } => vec![fovy, z_near, z_far],
+        Projection::Orthographic {
+            left,
+            right,
+            bottom,
+            top,
+            z_near,
+            z_far,
+        } => vec![left, right, bottom, top, z_near, z_far],
+    };
+    camera
+        .position
+        .iter()
+        .chain(&camera.center)
+        .chain(&camera.up)
+        .chain(&projection)
+        .all(|v| v.is_finite())
+}
+
+fn camera_form(camera: &Camera) -> String {
+    let vec3 = |v: [f32; 3]| {
+        format!(
+            "(x {}) (y {}) (z {})",
+            float_literal(v[0]),
+            float_literal(v[1]),
+            float_literal(v[2])
+        )
+    };
+    let projection = match camera.projection {
+        Projection::Perspective {
+            fovy,
+            z_near,
+            z_far,
+        } => format!(
+            "(perspective (fovy {}) (z-near {}) (z-far {}))",
+            float_literal(fovy),
+            float_literal(z_near),
+            float_literal(z_far)
+        ),
+        Projection::Orthographic {
+            left,
+            right,
+            bottom,
+            top,
+            z_near,
+            z_far,
+        } => format!(
+            "(orthographic (left {}) (right {}) (bottom {}) (top {}) (z-near {}) (z-far {}))",
+            float_literal(left),
+            float_literal(right),
+            float_literal(bottom),
+            float_literal(top),
+            float_literal(z_near),
+            float_literal(z_far)
+        ),
+    };
+    format!(
+        "(def {} (camera\n  (position {})\n  (center {})\n  (up {})\n  {}))",
+        SCRIPT_CAMERA,
+        vec3(camera.position),
+        vec3(camera.center),
+        vec3(camera.up),
+        projection
+    )
+}
+
+/// `perspective` only takes float literals, which need a decimal point.
+fn float_literal(value: f32) -> String {
+    let literal = value.to_string();
+    if literal.contains('.') {
+        literal
+    } else {
+        format!("{}.0", literal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera_at(position: [f32; 3]) -> Camera {
+        Camera {
+            position,
+            ..Camera::default()
+        }
+    }
+
+    #[test]
+    fn appends_the_camera_once() {
+        let mut code = "(draw m1)".to_string();
+        copy_to_script(&mut code, &camera_at([1.0, 2.0, 3.0])).unwrap();
+        assert!(code.starts_with("(draw m1)\n(def view-camera (camera\n  (position (x 1.0) (y 2.0) (z 3.0))"));
+        copy_to_script(&mut code, &camera_at([4.0, 5.0, 6.5])).unwrap();
+        assert_eq!(code.matches("(def view-camera").count(), 1);
+        assert!(code.contains("(position (x 4.0) (y 5.0) (z 6.5))"));
+        assert!(code.starts_with("(draw m1)\n"));
+    }
+
+    #[test]
+    fn replaces_only_the_top_level_form() {
+        let mut code = concat!(
+            "(def note \"(def view-camera (\")\n",
+            "(def view-camera (camera (position (x 0.0) (y 0.0) (z 9.0))))\n",
+            "(use-camera view-camera)",
+        )
+        .to_string();
+        copy_to_script(&mut code, &camera_at([1.0, 1.0, 1.0])).unwrap();
+        assert!(code.starts_with("(def note \"(def view-camera (\")\n(def view-camera (camera\n"));
+        assert!(code.ends_with("(z-far 10.0))))\n(use-camera view-camera)"));
+        assert!(!code.contains("9.0"));
+        let mut parser = Parser::new(&code);
+        assert_eq!(parser.parse().len(), 3);
+    }
+
+    #[test]
+    fn refuses_non_finite_cameras() {
+        let mut code = "(draw m1)".to_string();
+        assert!(copy_to_script(&mut code, &camera_at([f32::NAN, 0.0, 0.0])).is_err());
+        let far = Camera {
+            projection: Projection::Perspective {
+                fovy: 0.75,
+                z_near: 0.1,
+                z_far: f32::INFINITY,
+            },
+            ..Camera::default()
+        };
+        assert!(copy_to_script(&mut code, &far).is_err());
+        assert_eq!(code, "(draw m1)");
+    }
+
+    #[test]
+    fn float_literals_keep_a_decimal_point() {
+        assert_eq!(float_literal(3.0), "3.0");
+        assert_eq!(float_literal(-0.25), "-0.25");
+    }
+}
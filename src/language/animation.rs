@@ -0,0 +1,310 @@
+use crate::language::types::{Camera, InnerType, Projection, Transform, TransformOp};
+use crate::tvk_glm::{quaternion::Quat, vector::Vec3};
+
+/// How the value moves between two keys.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ease {
+    Linear,
+    /// Eases in and out of every key.
+    Cubic,
+    /// Holds each key until the next one.
+    Step,
+}
+
+impl Ease {
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Ease::Linear => t,
+            Ease::Cubic => t * t * (3.0 - 2.0 * t),
+            Ease::Step => 0.0,
+        }
+    }
+}
+
+/// What happens once the time goes past the last key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Loop {
+    /// Holds the last key.
+    Once,
+    Repeat,
+    /// Plays backwards to the first key, then forwards again.
+    PingPong,
+}
+
+/// Keys sorted by time, sampled with `sample`.
+pub struct Track {
+    pub keys: Vec<(f32, InnerType)>,
+    pub ease: Ease,
+    pub looping: Loop,
+}
+
+impl Track {
+    /// Time of the last key.
+    pub fn end(&self) -> f32 {
+        self.keys.last().map_or(0.0, |(time, _)| *time)
+    }
+
+    pub fn sample(&self, time: f32) -> Option<InnerType> {
+        let (first, last) = (self.keys.first()?.0, self.end());
+        let span = last - first;
+        let time = if span <= 0.0 {
+            first
+        } else {
+            match self.looping {
+                Loop::Once => time.clamp(first, last),
+                Loop::Repeat => first + (time - first).rem_euclid(span),
+                Loop::PingPong => {
+                    let t = (time - first).rem_euclid(2.0 * span);
+                    first + if t > span { 2.0 * span - t } else { t }
+                }
+            }
+        };
+        // Last key at or before `time`.
+        let i = self.keys.partition_point(|(key, _)| *key <= time).max(1) - 1;
+        let (t0, a) = &self.keys[i];
+        let (t1, b) = match self.keys.get(i + 1) {
+            Some(next) => next,
+            None => return Some(a.clone()),
+        };
+        let t = self.ease.apply(((time - t0) / (t1 - t0)).clamp(0.0, 1.0));
+        Some(interpolate(a, b, t).unwrap_or_else(|| a.clone()))
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp_array<const N: usize>(a: [f32; N], b: [f32; N], t: f32) -> [f32; N] {
+    let mut result = a;
+    for (r, b) in result.iter_mut().zip(b) {
+        *r = lerp(*r, b, t);
+    }
+    result
+}
+
+/// Value `t` of the way from `a` to `b`, or `None` when they can't be blended
+/// (different kinds of values, transforms with different steps...).
+pub fn interpolate(a: &InnerType, b: &InnerType, t: f32) -> Option<InnerType> {
+    let value = match (a, b) {
+        (InnerType::Float(a), InnerType::Float(b)) => InnerType::Float(lerp(*a, *b, t)),
+        (InnerType::UInt(a), InnerType::UInt(b)) => InnerType::Float(lerp(*a as f32, *b as f32, t)),
+        (InnerType::Float(a), InnerType::UInt(b)) => InnerType::Float(lerp(*a, *b as f32, t)),
+        (InnerType::UInt(a), InnerType::Float(b)) => InnerType::Float(lerp(*a as f32, *b, t)),
+        (InnerType::Position(a), InnerType::Position(b)) => InnerType::Position(lerp_array(*a, *b, t)),
+        (InnerType::Vec3(a), InnerType::Vec3(b)) => InnerType::Vec3(lerp_array(*a, *b, t)),
+        (InnerType::UV(a), InnerType::UV(b)) => InnerType::UV(lerp_array(*a, *b, t)),
+        (InnerType::Color(a), InnerType::Color(b)) => InnerType::Color(lerp_array(*a, *b, t)),
+        (InnerType::TransformOp(a), InnerType::TransformOp(b)) => {
+            InnerType::TransformOp(interpolate_op(a, b, t)?)
+        }
+        (InnerType::Transform(a), InnerType::Transform(b)) if a.ops.len() == b.ops.len() => {
+            let ops = a
+                .ops
+                .iter()
+                .zip(&b.ops)
+                .map(|(a, b)| interpolate_op(a, b, t))
+                .collect::<Option<_>>()?;
+            InnerType::Transform(Transform { ops })
+        }
+        (InnerType::Projection(a), InnerType::Projection(b)) => {
+            InnerType::Projection(interpolate_projection(a, b, t)?)
+        }
+        (InnerType::Camera(a), InnerType::Camera(b)) => InnerType::Camera(Camera {
+            position: lerp_array(a.position, b.position, t),
+            center: lerp_array(a.center, b.center, t),
+            up: lerp_array(a.up, b.up, t),
+            projection: interpolate_projection(&a.projection, &b.projection, t)?,
+        }),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn interpolate_op(a: &TransformOp, b: &TransformOp, t: f32) -> Option<TransformOp> {
+    let op = match (a, b) {
+        (TransformOp::Translate(a), TransformOp::Translate(b)) => {
+            TransformOp::Translate(lerp_array(*a, *b, t))
+        }
+        (TransformOp::Scale(a), TransformOp::Scale(b)) => TransformOp::Scale(lerp_array(*a, *b, t)),
+        // Keys around the same axis turn by their angles, a full turn or more
+        // included. Otherwise the shortest arc is taken.
+        (TransformOp::Rotate(angle_a, axis_a), TransformOp::Rotate(angle_b, axis_b))
+            if same_axis(*axis_a, *axis_b) =>
+        {
+            TransformOp::Rotate(lerp(*angle_a, *angle_b, t), *axis_a)
+        }
+        (TransformOp::Rotate(angle_a, axis_a), TransformOp::Rotate(angle_b, axis_b)) => {
+            let a = Quat::from_axis_angle(Vec3::from(*axis_a), *angle_a);
+            let b = Quat::from_axis_angle(Vec3::from(*axis_b), *angle_b);
+            let (axis, angle) = a.slerp(b, t).to_axis_angle();
+            TransformOp::Rotate(angle, axis.into())
+        }
+        (TransformOp::Matrix(a), TransformOp::Matrix(b)) => {
+            let mut matrix = *a;
+            for (column, b) in matrix.iter_mut().zip(b) {
+                *column = lerp_array(*column, *b, t);
+            }
+            TransformOp::Matrix(matrix)
+        }
+        _ => return None,
+    };
+    Some(op)
+}
+
+fn same_axis(a: [f32; 3], b: [f32; 3]) -> bool {
+    let (a, b) = (Vec3::from(a).normalize(), Vec3::from(b).normalize());
+    a.dot(b) > 1.0 - 1e-6
+}
+
+fn interpolate_projection(a: &Projection, b: &Projection, t: f32) -> Option<Projection> {
+    let projection = match (*a, *b) {
+        (
+            Projection::Perspective {
+                fovy: fovy_a,
+                z_near: near_a,
+                z_far: far_a,
+            },
+            Projection::Perspective {
+                fovy: fovy_b,
+                z_near: near_b,
+                z_far: far_b,
+            },
+        ) => Projection::Perspective {
+            fovy: lerp(fovy_a, fovy_b, t),
+            z_near: lerp(near_a, near_b, t),
+            z_far: lerp(far_a, far_b, t),
+        },
+        (
+            Projection::Orthographic {
+                left: left_a,
+                right: right_a,
+                bottom: bottom_a,
+                top: top_a,
+                z_near: near_a,
+                z_far: far_a,
+            },
+            Projection::Orthographic {
+                left: left_b,
+                right: right_b,
+                bottom: bottom_b,
+                top: top_b,
+                z_near: near_b,
+                z_far: far_b,
+            },
+        ) => Projection::Orthographic {
+            left: lerp(left_a, left_b, t),
+            right: lerp(right_a, right_b, t),
+            bottom: lerp(bottom_a, bottom_b, t),
+            top: lerp(top_a, top_b, t),
+            z_near: lerp(near_a, near_b, t),
+            z_far: lerp(far_a, far_b, t),
+        },
+        _ => return None,
+    };
+    Some(projection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(keys: &[(f32, f32)], ease: Ease, looping: Loop) -> Track {
+        Track {
+            keys: keys.iter().map(|&(time, v)| (time, InnerType::Float(v))).collect(),
+            ease,
+            looping,
+        }
+    }
+
+    fn sample(track: &Track, time: f32) -> f32 {
+        match track.sample(time) {
+            Some(InnerType::Float(v)) => v,
+            other => panic!("expected a float, got {:?}", other),
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn once_holds_the_ends() {
+        let track = track(&[(0.0, 0.0), (2.0, 10.0)], Ease::Linear, Loop::Once);
+        assert_close(sample(&track, 1.0), 5.0);
+        assert_close(sample(&track, -1.0), 0.0);
+        assert_close(sample(&track, 5.0), 10.0);
+    }
+
+    #[test]
+    fn repeat_wraps_around() {
+        let track = track(&[(0.0, 0.0), (2.0, 10.0)], Ease::Linear, Loop::Repeat);
+        assert_close(sample(&track, 3.0), 5.0);
+        assert_close(sample(&track, 4.5), 2.5);
+        assert_close(sample(&track, -1.0), 5.0);
+    }
+
+    #[test]
+    fn ping_pong_plays_backwards() {
+        let track = track(&[(0.0, 0.0), (2.0, 10.0)], Ease::Linear, Loop::PingPong);
+        assert_close(sample(&track, 2.5), 7.5);
+        assert_close(sample(&track, 3.0), 5.0);
+        assert_close(sample(&track, 4.0), 0.0);
+        assert_close(sample(&track, 5.0), 5.0);
+    }
+
+    #[test]
+    fn step_holds_each_key() {
+        let track = track(&[(0.0, 0.0), (1.0, 10.0), (2.0, 20.0)], Ease::Step, Loop::Once);
+        assert_close(sample(&track, 0.9), 0.0);
+        assert_close(sample(&track, 1.0), 10.0);
+        assert_close(sample(&track, 1.5), 10.0);
+        assert_close(sample(&track, 2.0), 20.0);
+    }
+
+    #[test]
+    fn single_key_is_constant() {
+        for looping in [Loop::Once, Loop::Repeat, Loop::PingPong] {
+            let track = track(&[(1.0, 7.0)], Ease::Linear, looping);
+            assert_close(sample(&track, 0.0), 7.0);
+            assert_close(sample(&track, 10.0), 7.0);
+        }
+        assert!(track(&[], Ease::Linear, Loop::Once).sample(0.0).is_none());
+    }
+
+    #[test]
+    fn mismatched_kinds_keep_the_first_key() {
+        let track = Track {
+            keys: vec![(0.0, InnerType::Float(1.0)), (1.0, InnerType::Vec3([2.0, 2.0, 2.0]))],
+            ease: Ease::Linear,
+            looping: Loop::Once,
+        };
+        assert_close(sample(&track, 0.5), 1.0);
+    }
+
+    #[test]
+    fn rotations_around_the_same_axis_turn_fully() {
+        let a = TransformOp::Rotate(0.0, [0.0, 1.0, 0.0]);
+        let b = TransformOp::Rotate(std::f32::consts::TAU, [0.0, 2.0, 0.0]);
+        match interpolate_op(&a, &b, 0.5) {
+            Some(TransformOp::Rotate(angle, axis)) => {
+                assert_close(angle, std::f32::consts::PI);
+                assert_eq!(axis, [0.0, 1.0, 0.0]);
+            }
+            other => panic!("expected a rotation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rotations_around_different_axes_take_the_shortest_arc() {
+        let a = TransformOp::Rotate(0.0, [1.0, 0.0, 0.0]);
+        let b = TransformOp::Rotate(std::f32::consts::FRAC_PI_2, [0.0, 0.0, 1.0]);
+        match interpolate_op(&a, &b, 0.5) {
+            Some(TransformOp::Rotate(angle, axis)) => {
+                assert_close(angle, std::f32::consts::FRAC_PI_4);
+                assert_close(axis[2], 1.0);
+            }
+            other => panic!("expected a rotation, got {:?}", other),
+        }
+    }
+}
@@ -1,13 +1,61 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, marker::PhantomData, path::{Path, PathBuf}, sync::Arc, time::SystemTime};
 
+use vulkano::pipeline::graphics::input_assembly::PrimitiveTopology;
+
+use crate::assets::export::{Export, ExportFormat};
+use crate::assets::{gltf, obj, shapes};
+use crate::language::animation::{Ease, Loop, Track};
 use crate::language::types::*;
 use crate::rendering_pipeline::MSAAPipeline;
+use crate::tvk_glm::identity_mat4;
+
+/// Variable sampled by `surface`, `curve` and `plot-2d`, bound to `steps + 1`
+/// evenly spaced values from `min` to `max`.
+#[derive(Clone, Copy)]
+struct SampleRange<'a> {
+    name: &'a str,
+    min: f32,
+    max: f32,
+    steps: u32,
+}
+
+impl SampleRange<'_> {
+    fn value(&self, step: u32) -> f32 {
+        self.min + (self.max - self.min) * step as f32 / self.steps as f32
+    }
+}
+
+/// Functions handled by `eval_math`.
+const MATH_OPERATORS: [&str; 19] = [
+    "+", "-", "*", "/", "min", "max", "pow", "atan2", "sin", "cos", "tan", "asin", "acos", "atan",
+    "sqrt", "exp", "ln", "abs", "floor",
+];
 
 pub struct Interpreter<'a> {
-    pub bindings: HashMap<&'a str, InnerType>,
+    pub bindings: HashMap<String, InnerType>,
+    pub interpreting_mode: InterpretingMode,
+    pub errors: Vec<String>,
+    /// Time of the last key of the `animate` forms evaluated so far.
+    pub animation_end: f32,
+    /// `export-obj` and `export-ply` forms, written by the caller.
+    pub exports: Vec<Export>,
+    /// Bindings own their names, the parsed source is only borrowed while
+    /// evaluating.
+    source: PhantomData<&'a str>,
 }
 
 impl<'a> Interpreter<'a> {
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::from([("pi".to_string(), InnerType::Float(std::f32::consts::PI))]),
+            interpreting_mode: InterpretingMode::Continuous,
+            errors: Vec::new(),
+            animation_end: 0.0,
+            exports: Vec::new(),
+            source: PhantomData,
+        }
+    }
+
     pub fn eval(
         &mut self,
         exprs: &TvkObject<'a>,
@@ -19,11 +67,16 @@ impl<'a> Interpreter<'a> {
                     return None;
                 }
                 match &l[0] {
+                    TvkObject::Atom("config") => {
+                        self.eval_config(l, pipeline);
+                        return None;
+                    },
                     TvkObject::Atom("def") => {
                         if let Some(TvkObject::Atom(key)) = &l.get(1) {
                             if let Some(e) = &l.get(2) {
-                                if let Some(val) = self.eval(e, pipeline) {
-                                    self.bindings.insert(key, val);
+                                if let Some(mut val) = self.eval(e, pipeline) {
+                                    Self::name_models(&mut val, key);
+                                    self.bindings.insert(key.to_string(), val);
                                 }
                             }
                         }
@@ -32,6 +85,9 @@ impl<'a> Interpreter<'a> {
                     TvkObject::Atom("position") => {
                         return self.eval_pos(l, pipeline);
                     },
+                    TvkObject::Atom("normal") => {
+                        return self.eval_pos(l, pipeline);
+                    },
                     TvkObject::Atom("uv") => {
                         return self.eval_uv(l, pipeline);
                     },
@@ -47,7 +103,7 @@ impl<'a> Interpreter<'a> {
                     TvkObject::Atom("color") => {
                         return match &l.get(1) {
                             Some(TvkObject::Color(c)) => Some(InnerType::Color(*c)),
-                            Some(TvkObject::Atom(ident)) => self.bindings.get(ident).cloned(),
+                            Some(TvkObject::Atom(ident)) => self.bindings.get(*ident).cloned(),
                             _ => None,
                         }
                     },
@@ -84,38 +140,20 @@ impl<'a> Interpreter<'a> {
                     TvkObject::Atom("perspective") => {
                         return self.eval_perspective(l, pipeline);
                     },
+                    TvkObject::Atom("orthographic") => {
+                        return self.eval_orthographic(l, pipeline);
+                    },
                     TvkObject::Atom("camera") => {
                         return self.eval_camera(l, pipeline);
                     },
                     TvkObject::Atom("transform") => {
                         return self.eval_transform(l, pipeline);
                     },
-                    TvkObject::Atom("translate") => {
-                        if l.len() == 2 {
-                            if let Some(InnerType::Vec3(vector)) = self.eval(&l[1], pipeline) {
-                                return Some(InnerType::Vec3(vector));
-                            }
-                        } else {
-                            return None;
-                        }
-                    },
-                    TvkObject::Atom("scale") => {
-                        if l.len() == 2 {
-                            if let Some(InnerType::Vec3(vector)) = self.eval(&l[1], pipeline) {
-                                return Some(InnerType::Vec3(vector));
-                            }
-                        } else {
-                            return None;
-                        }
-                    },
-                    TvkObject::Atom("rotate") => {
-                        if l.len() == 2 {
-                            if let Some(InnerType::Vec3(vector)) = self.eval(&l[1], pipeline) {
-                                return Some(InnerType::Vec3(vector));
-                            }
-                        } else {
-                            return None;
-                        }
+                    TvkObject::Atom("translate")
+                    | TvkObject::Atom("scale")
+                    | TvkObject::Atom("rotate")
+                    | TvkObject::Atom("matrix") => {
+                        return self.eval_transform_op(l, pipeline);
                     },
                     TvkObject::Atom("topology") => {
                         return self.eval_topology(l);
@@ -123,33 +161,83 @@ impl<'a> Interpreter<'a> {
                     TvkObject::Atom("model") => {
                         return self.eval_model(l, pipeline);
                     },
-                    TvkObject::Atom("draw") => {
-                        if l.len() > 1 {
-                            for drawable in &l[1..] {
-                                match self.eval(drawable, pipeline) {
-                                    Some(InnerType::Model(m)) => {
-                                        pipeline.receive_model(m);
-                                        return None;
-                                    },
-                                    Some(InnerType::VertexBuffer(vb)) => {
-                                        pipeline.receive_vertex_buffer(vb);
-                                        return None;
-                                    },
-                                    _ => return None,
-                                }
+                    TvkObject::Atom("list") => {
+                        let mut items = Vec::new();
+                        for item in &l[1..] {
+                            if let Some(val) = self.eval(item, pipeline) {
+                                items.push(val);
                             }
-                        } else {
-                            return None;
                         }
+                        return Some(InnerType::List(items));
+                    },
+                    TvkObject::Atom("draw") => {
+                        self.eval_draw(l, pipeline);
+                        return None;
+                    },
+                    TvkObject::Atom("animate") => {
+                        self.eval_animate(l, pipeline);
+                        return None;
+                    },
+                    TvkObject::Atom("node") => {
+                        return self.eval_node(l, pipeline);
+                    },
+                    TvkObject::Atom("use-camera") => {
+                        self.eval_use_camera(l, pipeline);
+                        return None;
+                    },
+                    TvkObject::Atom("viewport") => {
+                        self.eval_viewport(l, pipeline);
+                        return None;
+                    },
+                    TvkObject::Atom("draw-instanced") => {
+                        self.eval_draw_instanced(l, pipeline);
+                        return None;
                     },
                     TvkObject::Atom("texture") => {
-                        if l.len() == 2 {
-                            if let TvkObject::Atom(path) = &l[1] {
-                                if let Some((data, dims)) = pipeline.load_texture_image(path){
-                                    return Some(InnerType::Texture((data, dims)));
-                                }
-                            }
-                        }
+                        return self.eval_texture(l, pipeline);
+                    }
+                    TvkObject::Atom("sampler") => {
+                        return self.eval_sampler(l, pipeline);
+                    }
+                    TvkObject::Atom("material") => {
+                        return self.eval_material(l, pipeline);
+                    }
+                    TvkObject::Atom("light") => {
+                        return self.eval_light(l, pipeline);
+                    }
+                    TvkObject::Atom("load-obj") => {
+                        return self.eval_load_obj(l, pipeline);
+                    }
+                    TvkObject::Atom("load-gltf") => {
+                        return self.eval_load_gltf(l, pipeline);
+                    }
+                    TvkObject::Atom("cube")
+                    | TvkObject::Atom("sphere")
+                    | TvkObject::Atom("cylinder")
+                    | TvkObject::Atom("cone")
+                    | TvkObject::Atom("torus")
+                    | TvkObject::Atom("plane") => {
+                        return self.eval_shape(l, pipeline);
+                    }
+                    TvkObject::Atom(op) if MATH_OPERATORS.contains(op) => {
+                        return self.eval_math(l, pipeline);
+                    }
+                    TvkObject::Atom("surface") => {
+                        return self.eval_surface(l, pipeline);
+                    }
+                    TvkObject::Atom("plot-2d") | TvkObject::Atom("curve") => {
+                        return self.eval_curve(l, pipeline);
+                    }
+                    TvkObject::Atom("export-obj") | TvkObject::Atom("export-ply") => {
+                        self.eval_export(l, pipeline);
+                        return None;
+                    }
+                    TvkObject::Atom("normals") => {
+                        return match l.get(1) {
+                            Some(TvkObject::Atom("flat")) => Some(InnerType::Normals(NormalMode::Flat)),
+                            Some(TvkObject::Atom("smooth")) => Some(InnerType::Normals(NormalMode::Smooth)),
+                            _ => None,
+                        };
                     }
                     _ => return None,
                 }
@@ -164,6 +252,44 @@ impl<'a> Interpreter<'a> {
         }
     }
 
+    fn eval_config(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline,
+        ) {
+        for option in &expr[1..] {
+            if let TvkObject::List(option) = option {
+                match (option.first(), option.get(1)) {
+                    (Some(TvkObject::Atom("primitive")), Some(primitive)) => {
+                        if let Some(primitive) = Self::eval_primitive(primitive) {
+                            pipeline.change_topology(primitive);
+                        }
+                    },
+                    (Some(TvkObject::Atom("interpreting-mode")), Some(TvkObject::Atom(mode))) => {
+                        match *mode {
+                            "continuous" => self.interpreting_mode = InterpretingMode::Continuous,
+                            "manual" => self.interpreting_mode = InterpretingMode::Manual,
+                            _ => (),
+                        }
+                    },
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn eval_primitive(expr: &TvkObject<'a>) -> Option<PrimitiveTopology> {
+        match expr {
+            TvkObject::Atom("default") => Some(PrimitiveTopology::TriangleList),
+            TvkObject::Atom("triangle-list") => Some(PrimitiveTopology::TriangleList),
+            TvkObject::Atom("triangle-strip") => Some(PrimitiveTopology::TriangleStrip),
+            TvkObject::Atom("line-list") => Some(PrimitiveTopology::LineList),
+            TvkObject::Atom("line-strip") => Some(PrimitiveTopology::LineStrip),
+            TvkObject::Atom("point-list") => Some(PrimitiveTopology::PointList),
+            _ => None,
+        }
+    }
+
     fn eval_pos(
         &mut self,
         expr: &Vec<TvkObject<'a>>,
@@ -178,11 +304,7 @@ impl<'a> Interpreter<'a> {
             let mut vec3: [f32; 3] = [0.0, 0.0, 0.0];
             let mut i = 0;
             for list in [&x_list, &y_list, &z_list] {
-                match list.get(1) {
-                    Some(TvkObject::FloatLiteral(n)) => vec3[i] = *n,
-                    Some(TvkObject::UIntLiteral(n)) => vec3[i] = *n as f32,
-                    _ => return None,
-                }
+                vec3[i] = self.eval_number(list.get(1)?, pipeline)?;
                 i += 1;
             }
             return Some(InnerType::Position(vec3));
@@ -327,8 +449,19 @@ impl<'a> Interpreter<'a> {
                                 Some(InnerType::Color(color)) =>
                                     Some(InnerType::Vertex(Vertex { position, color})),
                                 Some(InnerType::UV(uv)) => {
-                                    Some(InnerType::TextureVertex(
-                                            TextureVertex { position, uv }))
+                                    let mut vertex = TextureVertex {
+                                        position,
+                                        uv,
+                                        ..Default::default()
+                                    };
+                                    if let Some(e3) = &expr.get(3) {
+                                        match self.eval(&e3, pipeline) {
+                                            Some(InnerType::Position(normal)) =>
+                                                vertex.normal = normal,
+                                            _ => return None,
+                                        }
+                                    }
+                                    Some(InnerType::TextureVertex(vertex))
                                 },
                                 _ => None,
                             };
@@ -396,7 +529,11 @@ impl<'a> Interpreter<'a> {
             if let Some(InnerType::Float(fovy)) = self.eval(&expr[1], pipeline) {
                 if let Some(InnerType::Float(z_near)) = self.eval(&expr[2], pipeline) {
                     if let Some(InnerType::Float(z_far)) = self.eval(&expr[3], pipeline) {
-                        return Some(InnerType::Perspective([fovy, z_near, z_far]));
+                        return Some(InnerType::Projection(Projection::Perspective {
+                            fovy,
+                            z_near,
+                            z_far,
+                        }));
                     }
                 }
             }
@@ -406,6 +543,44 @@ impl<'a> Interpreter<'a> {
         return None;
     }
 
+    /// `(orthographic (left ..) (right ..) (bottom ..) (top ..) (z-near ..) (z-far ..))`,
+    /// missing bounds default to a 2x2 box from 0.1 to 100.
+    fn eval_orthographic(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        if let [_, TvkObject::Atom(_)] = expr.as_slice() {
+            return self.eval(&expr[1], pipeline);
+        }
+        let mut bounds = [-1.0, 1.0, -1.0, 1.0, 0.1, 100.0];
+        for option in &expr[1..] {
+            let option = match option {
+                TvkObject::List(option) if option.len() == 2 => option,
+                _ => return None,
+            };
+            let index = match &option[0] {
+                TvkObject::Atom("left") => 0,
+                TvkObject::Atom("right") => 1,
+                TvkObject::Atom("bottom") => 2,
+                TvkObject::Atom("top") => 3,
+                TvkObject::Atom("z-near") => 4,
+                TvkObject::Atom("z-far") => 5,
+                _ => return None,
+            };
+            bounds[index] = self.eval_number(&option[1], pipeline)?;
+        }
+        let [left, right, bottom, top, z_near, z_far] = bounds;
+        Some(InnerType::Projection(Projection::Orthographic {
+            left,
+            right,
+            bottom,
+            top,
+            z_near,
+            z_far,
+        }))
+    }
+
     fn eval_camera(
         &mut self,
         expr: &Vec<TvkObject<'a>>,
@@ -415,14 +590,14 @@ impl<'a> Interpreter<'a> {
             if let Some(InnerType::Position(position)) = self.eval(&expr[1], pipeline) {
                 if let Some(InnerType::Position(center)) = self.eval(&expr[2], pipeline) {
                     if let Some(InnerType::Position(up)) = self.eval(&expr[3], pipeline) {
-                        if let Some(InnerType::Perspective(perspective)) =
+                        if let Some(InnerType::Projection(projection)) =
                             self.eval(&expr[4], pipeline)
                         {
                             return Some(InnerType::Camera(Camera {
                                 position,
                                 center,
                                 up,
-                                perspective,
+                                projection,
                             }));
                         }
                     }
@@ -436,41 +611,97 @@ impl<'a> Interpreter<'a> {
         return None;
     }
 
+    /// `(transform op...)`, ops being `translate`, `scale`, `rotate` and
+    /// `matrix` forms or other transforms, applied in the written order.
     fn eval_transform(
         &mut self,
         expr: &Vec<TvkObject<'a>>,
         pipeline: &mut MSAAPipeline
         ) -> Option<InnerType> {
-        if expr.len() == 4 {
-            if let Some(InnerType::Vec3(translate)) = self.eval(&expr[1], pipeline) {
-                if let Some(InnerType::Vec3(scale)) = self.eval(&expr[2], pipeline) {
-                    if let Some(InnerType::Rotate(rotate)) = self.eval(&expr[3], pipeline) {
-                        return Some(InnerType::Transform(Transform {
-                            translate,
-                            scale,
-                            rotate,
-                        }));
-                    }
-                }
-            } else if let TvkObject::Atom("default") = &expr[1] {
-                return Some(InnerType::Transform(Transform::default()));
+        let mut transform = Transform::default();
+        for op in &expr[1..] {
+            if let TvkObject::Atom("default") = op {
+                continue;
             }
-            return self.eval(&expr[1], pipeline);
-        } else if expr.len() == 2 {
-            match &expr[1] {
-                TvkObject::Atom("default") =>
-                    return Some(InnerType::Transform(Transform::default())),
-                TvkObject::Atom(ident) => {
-                    if let Some(InnerType::Transform(t)) = self.bindings.get(ident) {
-                        return Some(InnerType::Transform(t.clone()));
-                    }
+            match self.eval(op, pipeline) {
+                Some(InnerType::TransformOp(op)) => transform.ops.push(op),
+                Some(InnerType::Transform(t)) => transform.ops.extend(t.ops),
+                _ => {
+                    self.errors.push(
+                        "transform: expected translate, scale, rotate, matrix or a transform".to_string(),
+                    );
+                    return None;
                 },
-                _ => return None,
             }
         }
-        return None;
+        Some(InnerType::Transform(transform))
     }
-    
+
+    /// `(translate v)`, `(scale v)` or `(scale n)`, `(rotate angle axis)` with
+    /// the angle in radians (both can be wrapped as `(angle ..)` and
+    /// `(axis ..)`), and `(matrix (m00 m01 .. m33))` with the matrix
+    /// written row by row.
+    fn eval_transform_op(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        let op = match (&expr[0], &expr[1..]) {
+            (TvkObject::Atom("translate"), [v]) => match self.eval(v, pipeline) {
+                Some(InnerType::Vec3(v)) => Some(TransformOp::Translate(v)),
+                _ => None,
+            },
+            (TvkObject::Atom("scale"), [v]) => match self.eval(v, pipeline) {
+                Some(InnerType::Vec3(v)) => Some(TransformOp::Scale(v)),
+                Some(InnerType::Float(n)) => Some(TransformOp::Scale([n; 3])),
+                Some(InnerType::UInt(n)) => Some(TransformOp::Scale([n as f32; 3])),
+                _ => None,
+            },
+            (TvkObject::Atom("rotate"), [angle, axis]) => {
+                let angle = Self::named_arg(angle, "angle");
+                let axis = Self::named_arg(axis, "axis");
+                match (self.eval_number(angle, pipeline), self.eval(axis, pipeline)) {
+                    (Some(angle), Some(InnerType::Vec3(axis))) => Some(TransformOp::Rotate(angle, axis)),
+                    _ => None,
+                }
+            },
+            (TvkObject::Atom("matrix"), [TvkObject::List(values)]) if values.len() == 16 => {
+                let mut matrix = [[0.0; 4]; 4];
+                let mut complete = true;
+                for (i, value) in values.iter().enumerate() {
+                    match self.eval_number(value, pipeline) {
+                        // Written by rows, stored by columns.
+                        Some(n) => matrix[i % 4][i / 4] = n,
+                        None => complete = false,
+                    }
+                }
+                complete.then_some(TransformOp::Matrix(matrix))
+            },
+            _ => None,
+        };
+        match op {
+            Some(op) => Some(InnerType::TransformOp(op)),
+            None => {
+                let usage = match &expr[0] {
+                    TvkObject::Atom("translate") => "(translate vec3)",
+                    TvkObject::Atom("scale") => "(scale vec3) or (scale n)",
+                    TvkObject::Atom("rotate") => "(rotate angle axis)",
+                    _ => "(matrix (16 numbers))",
+                };
+                self.errors.push(format!("expected {}", usage));
+                None
+            },
+        }
+    }
+
+    /// The value of `(name value)`, or `expr` itself when it isn't wrapped.
+    fn named_arg<'e>(expr: &'e TvkObject<'a>, name: &str) -> &'e TvkObject<'a> {
+        match expr {
+            TvkObject::List(l) if l.len() == 2 && matches!(l[0], TvkObject::Atom(n) if n == name) => &l[1],
+            _ => expr,
+        }
+    }
+
     fn eval_topology(
         &mut self,
         expr: &Vec<TvkObject<'a>>,
@@ -502,45 +733,1069 @@ impl<'a> Interpreter<'a> {
         }
     }
 
+    fn eval_texture(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        let path = Self::eval_path(expr.get(1)?)?;
+        let mut color_space = ColorSpace::Srgb;
+        for option in &expr[2..] {
+            if let TvkObject::List(option) = option {
+                match (option.first(), option.get(1)) {
+                    (Some(TvkObject::Atom("color-space")), Some(TvkObject::Atom("linear"))) => {
+                        color_space = ColorSpace::Linear;
+                    },
+                    (Some(TvkObject::Atom("color-space")), Some(TvkObject::Atom("srgb"))) => {
+                        color_space = ColorSpace::Srgb;
+                    },
+                    _ => (),
+                }
+            }
+        }
+        self.load_texture(Path::new(path), color_space, pipeline)
+            .map(InnerType::Texture)
+    }
+
+    fn eval_sampler(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        if let (2, Some(TvkObject::Atom(ident))) = (expr.len(), expr.get(1)) {
+            return match self.bindings.get(*ident) {
+                Some(InnerType::Sampler(s)) => Some(InnerType::Sampler(*s)),
+                _ => None,
+            };
+        }
+        let mut sampler = SamplerConfig::default();
+        for option in &expr[1..] {
+            if let TvkObject::List(option) = option {
+                match (option.first(), option.get(1)) {
+                    (Some(TvkObject::Atom("filter")), Some(TvkObject::Atom(filter))) => {
+                        sampler.filter = match *filter {
+                            "nearest" => SamplerFilter::Nearest,
+                            "linear" => SamplerFilter::Linear,
+                            _ => return None,
+                        };
+                    },
+                    (Some(TvkObject::Atom("wrap")), Some(TvkObject::Atom(wrap))) => {
+                        sampler.wrap = match *wrap {
+                            "repeat" => SamplerWrap::Repeat,
+                            "clamp" => SamplerWrap::Clamp,
+                            "mirror" => SamplerWrap::Mirror,
+                            _ => return None,
+                        };
+                    },
+                    (Some(TvkObject::Atom("anisotropy")), Some(n)) => {
+                        sampler.anisotropy = match self.eval(n, pipeline) {
+                            Some(InnerType::UInt(n)) => n.max(1),
+                            Some(InnerType::Float(n)) => (n as u32).max(1),
+                            _ => return None,
+                        };
+                    },
+                    (Some(TvkObject::Atom("mipmaps")), Some(TvkObject::Atom(mipmaps))) => {
+                        sampler.mipmaps = match *mipmaps {
+                            "on" => true,
+                            "off" => false,
+                            _ => return None,
+                        };
+                    },
+                    _ => return None,
+                }
+            }
+        }
+        Some(InnerType::Sampler(sampler))
+    }
+
+    fn eval_material(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        if let (2, Some(TvkObject::Atom(ident))) = (expr.len(), expr.get(1)) {
+            return match self.bindings.get(*ident) {
+                Some(InnerType::Material(m)) => Some(InnerType::Material(m.clone())),
+                _ => None,
+            };
+        }
+        let mut base_color = None;
+        let mut material = Material::default();
+        for option in &expr[1..] {
+            if let TvkObject::List(option) = option {
+                match (option.first(), option.get(1)) {
+                    (Some(TvkObject::Atom("base-color")), Some(e)) => {
+                        match self.eval(e, pipeline) {
+                            Some(InnerType::Texture(t)) => base_color = Some(t),
+                            _ => return None,
+                        }
+                    },
+                    (Some(TvkObject::Atom("normal-map")), Some(e)) => {
+                        match self.eval(e, pipeline) {
+                            Some(InnerType::Texture(t)) => material.normal_map = Some(t),
+                            _ => return None,
+                        }
+                    },
+                    (Some(TvkObject::Atom("tint")), Some(e)) => {
+                        match e {
+                            TvkObject::Color(c) => material.tint = *c,
+                            _ => match self.eval(e, pipeline) {
+                                Some(InnerType::Color(c)) => material.tint = c,
+                                _ => return None,
+                            },
+                        }
+                    },
+                    (Some(TvkObject::Atom("shininess")), Some(e)) => {
+                        material.shininess = self.eval_number(e, pipeline)?;
+                    },
+                    (Some(TvkObject::Atom("blend")), Some(TvkObject::Atom(blend))) => {
+                        material.blend = match *blend {
+                            "opaque" => BlendMode::Opaque,
+                            "alpha" => BlendMode::Alpha,
+                            "additive" => BlendMode::Additive,
+                            _ => return None,
+                        };
+                    },
+                    (Some(TvkObject::Atom("sampler")), Some(_)) => {
+                        match self.eval_sampler(option, pipeline) {
+                            Some(InnerType::Sampler(s)) => material.sampler = s,
+                            _ => return None,
+                        }
+                    },
+                    _ => return None,
+                }
+            }
+        }
+        material.base_color = base_color?;
+        Some(InnerType::Material(material))
+    }
+
+    fn eval_light(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        let kind_expr = match expr.get(1) {
+            Some(TvkObject::List(kind_expr)) => kind_expr,
+            Some(TvkObject::Atom(ident)) => {
+                return match self.bindings.get(*ident) {
+                    Some(InnerType::Light(light)) => Some(InnerType::Light(*light)),
+                    _ => None,
+                };
+            },
+            _ => return None,
+        };
+        let mut color = [1.0, 1.0, 1.0, 1.0];
+        let mut intensity = 1.0;
+        let mut position = [0.0, 0.0, 0.0];
+        let mut direction = [0.0, -1.0, 0.0];
+        let mut angle = 0.5;
+        for option in &kind_expr[1..] {
+            if let TvkObject::List(option) = option {
+                match (option.first(), option.get(1)) {
+                    (Some(TvkObject::Atom("position")), Some(_)) => {
+                        match self.eval_pos(option, pipeline) {
+                            Some(InnerType::Position(p)) => position = p,
+                            _ => return None,
+                        }
+                    },
+                    (Some(TvkObject::Atom("direction")), Some(_)) => {
+                        match self.eval_pos(option, pipeline) {
+                            Some(InnerType::Position(d)) => direction = d,
+                            _ => return None,
+                        }
+                    },
+                    (Some(TvkObject::Atom("angle")), Some(e)) => {
+                        angle = self.eval_number(e, pipeline)?;
+                    },
+                    (Some(TvkObject::Atom("intensity")), Some(e)) => {
+                        intensity = self.eval_number(e, pipeline)?;
+                    },
+                    (Some(TvkObject::Atom("color")), Some(TvkObject::Color(c))) => {
+                        color = *c;
+                    },
+                    (Some(TvkObject::Atom("color")), Some(e)) => {
+                        match self.eval(e, pipeline) {
+                            Some(InnerType::Color(c)) => color = c,
+                            _ => return None,
+                        }
+                    },
+                    _ => return None,
+                }
+            }
+        }
+        let kind = match kind_expr.first() {
+            Some(TvkObject::Atom("ambient")) => LightKind::Ambient,
+            Some(TvkObject::Atom("directional")) => LightKind::Directional { direction },
+            Some(TvkObject::Atom("point")) => LightKind::Point { position },
+            Some(TvkObject::Atom("spot")) => LightKind::Spot { position, direction, angle },
+            _ => return None,
+        };
+        Some(InnerType::Light(Light {
+            kind,
+            color,
+            intensity,
+        }))
+    }
+
+    fn eval_number(
+        &mut self,
+        expr: &TvkObject<'a>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<f32> {
+        match self.eval(expr, pipeline) {
+            Some(InnerType::Float(n)) => Some(n),
+            Some(InnerType::UInt(n)) => Some(n as f32),
+            _ => None,
+        }
+    }
+
     fn eval_model(
         &mut self,
         expr: &Vec<TvkObject<'a>>,
         pipeline: &mut MSAAPipeline
         ) -> Option<InnerType> {
-        if expr.len() == 7 {
-            if let Some(InnerType::TexVertexBuffer(vertices)) = self.eval(&expr[1], pipeline) {
-                if let Some(InnerType::IndexBuffer(indices)) = self.eval(&expr[2], pipeline) {
-                    if let Some(InnerType::Topology(topology)) = self.eval(&expr[3], pipeline) {
-                        if let Some(InnerType::Transform(transforms)) =
-                            self.eval(&expr[4], pipeline) {
-                                if let Some(InnerType::Camera(camera)) =
-                                    self.eval(&expr[5], pipeline) {
-                                        if let Some(InnerType::Texture(texture_data)) =
-                                            self.eval(&expr[6], pipeline) {
-                                                return Some(InnerType::Model(Model {
-                                                    vertices,
-                                                    indices,
-                                                    topology: "RESERVED_TRIANGLE_LIST_TEX".to_string(),
-                                                    transforms,
-                                                    camera,
-                                                    texture_data,
-                                                    texture: None,
-                                                }))
-                                            };
-                                    }
+        // A mesh takes the place of the vertex and index buffers.
+        let (mesh, rest) = match self.eval(expr.get(1)?, pipeline)? {
+            InnerType::Mesh(mesh) => (mesh, &expr[2..]),
+            InnerType::TexVertexBuffer(vertices) => match self.eval(expr.get(2)?, pipeline)? {
+                InnerType::IndexBuffer(indices) => {
+                    let group = MeshGroup {
+                        name: String::new(),
+                        vertices,
+                        indices,
+                        material: None,
+                        id: MeshId::new(),
+                    };
+                    (vec![group], &expr[3..])
+                },
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if rest.len() < 3 {
+            return None;
+        }
+        let topology = match self.eval(&rest[0], pipeline)? {
+            InnerType::Topology(topology) => topology,
+            _ => return None,
+        };
+        let transforms = match self.eval(&rest[1], pipeline)? {
+            InnerType::Transform(transforms) => transforms,
+            _ => return None,
+        };
+        // A camera before the material overrides the scene one.
+        let (camera, material, rest) = match self.eval(&rest[2], pipeline)? {
+            InnerType::Camera(camera) => (Some(camera), self.eval(rest.get(3)?, pipeline)?, &rest[1..]),
+            material => (None, material, rest),
+        };
+        let material = match material {
+            InnerType::Texture(base_color) => Material {
+                base_color,
+                ..Default::default()
+            },
+            InnerType::Material(material) => material,
+            _ => return None,
+        };
+        // Groups that brought their own material (e.g. from an MTL file) keep it.
+        let mut models: Vec<Model> = mesh
+            .into_iter()
+            .map(|group| Model {
+                name: group.name,
+                vertices: group.vertices,
+                indices: group.indices,
+                topology: topology.clone(),
+                transforms: transforms.clone(),
+                camera,
+                material: group.material.unwrap_or_else(|| material.clone()),
+                mesh: group.id,
+                ..Default::default()
+            })
+            .collect();
+        for option in &rest[3..] {
+            match self.eval(option, pipeline) {
+                Some(InnerType::Sampler(sampler)) => {
+                    for model in &mut models {
+                        model.material.sampler = sampler;
+                    }
+                },
+                Some(InnerType::Normals(mode)) => {
+                    for model in &mut models {
+                        model.generate_normals(mode);
+                    }
+                },
+                _ => (),
+            }
+        }
+        if models.len() == 1 {
+            return models.pop().map(InnerType::Model);
+        }
+        Some(InnerType::List(models.into_iter().map(InnerType::Model).collect()))
+    }
+
+    fn eval_load_obj(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        let path = Self::eval_path(expr.get(1)?)?;
+        let loaded = match obj::load_obj(Path::new(path)) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                self.errors.push(format!("load-obj: {}", e));
+                return None;
+            },
+        };
+        for warning in loaded.warnings {
+            self.errors.push(format!("load-obj: {}", warning));
+        }
+        let mut mesh = Vec::new();
+        for group in loaded.groups {
+            let material = group
+                .material
+                .map(|material| self.eval_obj_material(material, pipeline));
+            mesh.push(MeshGroup {
+                name: group.name,
+                vertices: group.vertices,
+                indices: group.indices,
+                material,
+                id: MeshId::new(),
+            });
+        }
+        Some(InnerType::Mesh(mesh))
+    }
+
+    fn eval_obj_material(
+        &mut self,
+        obj_material: obj::ObjMaterial,
+        pipeline: &mut MSAAPipeline
+        ) -> Material {
+        let [r, g, b] = obj_material.diffuse;
+        let mut material = Material {
+            tint: [r, g, b, obj_material.dissolve],
+            shininess: obj_material.shininess,
+            blend: if obj_material.dissolve < 1.0 {
+                BlendMode::Alpha
+            } else {
+                BlendMode::Opaque
+            },
+            ..Default::default()
+        };
+        if let Some(path) = obj_material.diffuse_map {
+            if let Some(texture) = self.load_texture(&path, ColorSpace::Srgb, pipeline) {
+                material.base_color = texture;
+            }
+        }
+        if let Some(path) = obj_material.normal_map {
+            material.normal_map = self.load_texture(&path, ColorSpace::Linear, pipeline);
+        }
+        material
+    }
+
+    /// Built-in shapes. Arguments are positional and the missing ones take a
+    /// default, so `(sphere)` is a unit diameter sphere.
+    fn eval_shape(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        let mut args = Vec::new();
+        for arg in &expr[1..] {
+            args.push(self.eval_number(arg, pipeline)?);
+        }
+        let arg = |i: usize, default: f32| args.get(i).copied().unwrap_or(default);
+        let count = |i: usize, default: u32| args.get(i).map(|n| *n as u32).unwrap_or(default);
+        let group = match &expr[0] {
+            TvkObject::Atom("cube") => shapes::cube(arg(0, 1.0)),
+            TvkObject::Atom("sphere") => shapes::sphere(arg(0, 0.5), count(1, 16), count(2, 32)),
+            TvkObject::Atom("cylinder") => shapes::cylinder(arg(0, 0.5), arg(1, 1.0), count(2, 32)),
+            TvkObject::Atom("cone") => shapes::cone(arg(0, 0.5), arg(1, 1.0), count(2, 32)),
+            TvkObject::Atom("torus") => {
+                shapes::torus(arg(0, 0.5), arg(1, 0.2), count(2, 32), count(3, 16))
+            },
+            TvkObject::Atom("plane") => shapes::plane(arg(0, 1.0), arg(1, 1.0), count(2, 1)),
+            _ => return None,
+        };
+        Some(InnerType::Mesh(vec![group]))
+    }
+
+    fn eval_math(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        let mut args = Vec::with_capacity(expr.len() - 1);
+        for arg in &expr[1..] {
+            args.push(self.eval_number(arg, pipeline)?);
+        }
+        let unary = |f: fn(f32) -> f32| match args[..] {
+            [x] => Some(f(x)),
+            _ => None,
+        };
+        let result: f32 = match &expr[0] {
+            TvkObject::Atom("+") => args.iter().sum(),
+            TvkObject::Atom("*") => args.iter().product(),
+            TvkObject::Atom("-") if args.len() == 1 => -args[0],
+            TvkObject::Atom("-") => args.iter().copied().reduce(|a, b| a - b)?,
+            TvkObject::Atom("/") => args.iter().copied().reduce(|a, b| a / b)?,
+            TvkObject::Atom("min") => args.iter().copied().reduce(f32::min)?,
+            TvkObject::Atom("max") => args.iter().copied().reduce(f32::max)?,
+            TvkObject::Atom("pow") if args.len() == 2 => args[0].powf(args[1]),
+            TvkObject::Atom("atan2") if args.len() == 2 => args[0].atan2(args[1]),
+            TvkObject::Atom("sin") => unary(f32::sin)?,
+            TvkObject::Atom("cos") => unary(f32::cos)?,
+            TvkObject::Atom("tan") => unary(f32::tan)?,
+            TvkObject::Atom("asin") => unary(f32::asin)?,
+            TvkObject::Atom("acos") => unary(f32::acos)?,
+            TvkObject::Atom("atan") => unary(f32::atan)?,
+            TvkObject::Atom("sqrt") => unary(f32::sqrt)?,
+            TvkObject::Atom("exp") => unary(f32::exp)?,
+            TvkObject::Atom("ln") => unary(f32::ln)?,
+            TvkObject::Atom("abs") => unary(f32::abs)?,
+            TvkObject::Atom("floor") => unary(f32::floor)?,
+            _ => return None,
+        };
+        Some(InnerType::Float(result))
+    }
+
+    /// Reads a sampling range `(name min max steps)`.
+    fn eval_range(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<SampleRange<'a>> {
+        let name = match expr.first()? {
+            TvkObject::Atom(name) => *name,
+            _ => return None,
+        };
+        let min = self.eval_number(expr.get(1)?, pipeline)?;
+        let max = self.eval_number(expr.get(2)?, pipeline)?;
+        let steps = match self.eval(expr.get(3)?, pipeline)? {
+            InnerType::UInt(steps) => steps,
+            InnerType::Float(steps) if steps >= 0.0 && steps.fract() == 0.0 => steps as u32,
+            _ => return None,
+        };
+        Some(SampleRange {
+            name,
+            min,
+            max,
+            steps: steps.max(1),
+        })
+    }
+
+    /// Splits the arguments of the sampling form `name` into its
+    /// `(var min max count)` ranges and the `(x expr)`, `(y expr)` and
+    /// `(z expr)` coordinates. Anything else is reported as an error.
+    fn eval_sampling_args<'e>(
+        &mut self,
+        name: &str,
+        expr: &'e [TvkObject<'a>],
+        pipeline: &mut MSAAPipeline
+        ) -> Option<(Vec<SampleRange<'a>>, [Option<&'e TvkObject<'a>>; 3])> {
+        let mut ranges = Vec::new();
+        let mut coords = [None; 3];
+        for (i, arg) in expr.iter().enumerate() {
+            let l = match arg {
+                TvkObject::List(l) => l,
+                _ => {
+                    self.errors.push(format!(
+                        "{}: argument {} must be a range (var min max count) or a coordinate (x expr)",
+                        name,
+                        i + 1
+                    ));
+                    return None;
+                },
+            };
+            match (l.first(), l.len()) {
+                (Some(TvkObject::Atom("x")), 2) => coords[0] = l.get(1),
+                (Some(TvkObject::Atom("y")), 2) => coords[1] = l.get(1),
+                (Some(TvkObject::Atom("z")), 2) => coords[2] = l.get(1),
+                (Some(TvkObject::Atom(var)), 4) if Self::is_range_var(var) => {
+                    match self.eval_range(l, pipeline) {
+                        Some(range) => ranges.push(range),
+                        None => {
+                            self.errors.push(format!(
+                                "{}: range {} needs numeric bounds and a whole number of steps",
+                                name, var
+                            ));
+                            return None;
+                        },
+                    }
+                },
+                _ => {
+                    self.errors.push(format!(
+                        "{}: argument {} must be a range (var min max count) or a coordinate (x expr)",
+                        name,
+                        i + 1
+                    ));
+                    return None;
+                },
+            }
+        }
+        Some((ranges, coords))
+    }
+
+    /// Range variables are plain names, not the math functions a coordinate
+    /// expression could start with.
+    fn is_range_var(var: &str) -> bool {
+        var.starts_with(|c: char| c.is_ascii_alphabetic()) && !MATH_OPERATORS.contains(&var)
+    }
+
+    /// Evaluates the coordinate expressions with the range variables bound to
+    /// `values`. Missing coordinates are 0.
+    fn eval_sample(
+        &mut self,
+        ranges: &[SampleRange<'a>],
+        values: &[f32],
+        coords: &[Option<&TvkObject<'a>>; 3],
+        pipeline: &mut MSAAPipeline
+        ) -> Option<[f32; 3]> {
+        for (range, value) in ranges.iter().zip(values) {
+            self.bindings.insert(range.name.to_string(), InnerType::Float(*value));
+        }
+        let mut point = [0.0; 3];
+        for (value, coord) in point.iter_mut().zip(coords) {
+            if let Some(coord) = coord {
+                *value = self.eval_number(coord, pipeline)?;
+            }
+        }
+        Some(point)
+    }
+
+    /// Runs `sample` with the range variables shadowing any existing binding,
+    /// which is put back afterwards.
+    fn with_ranges<T>(
+        &mut self,
+        ranges: &[SampleRange<'a>],
+        sample: impl FnOnce(&mut Self) -> Option<T>,
+        ) -> Option<T> {
+        let saved: Vec<_> = ranges
+            .iter()
+            .map(|range| (range.name, self.bindings.remove(range.name)))
+            .collect();
+        let result = sample(self);
+        for (name, val) in saved {
+            match val {
+                Some(val) => self.bindings.insert(name.to_string(), val),
+                None => self.bindings.remove(name),
+            };
+        }
+        result
+    }
+
+    /// `(surface (u 0 1 32) (v 0 1 32) (x expr) (y expr) (z expr))` samples a
+    /// grid over both ranges and triangulates it.
+    fn eval_surface(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        let (ranges, coords) = self.eval_sampling_args("surface", &expr[1..], pipeline)?;
+        let (u, v) = match ranges[..] {
+            [u, v] => (u, v),
+            _ => {
+                self.errors.push("surface: expected two ranges".to_string());
+                return None;
+            },
+        };
+        let points = self.with_ranges(&ranges, |interpreter| {
+            let mut points = Vec::with_capacity(((u.steps + 1) * (v.steps + 1)) as usize);
+            for i in 0..=v.steps {
+                for j in 0..=u.steps {
+                    let values = [u.value(j), v.value(i)];
+                    points.push(interpreter.eval_sample(&ranges, &values, &coords, pipeline)?);
+                }
+            }
+            Some(points)
+        });
+        match points {
+            Some(points) => Some(InnerType::Mesh(vec![shapes::surface(&points, v.steps, u.steps)])),
+            None => {
+                self.errors.push("surface: coordinates must evaluate to numbers".to_string());
+                None
+            },
+        }
+    }
+
+    /// `(curve (t 0 1 64) (x expr) (y expr) (z expr))` samples a line strip.
+    /// `(plot-2d (x -1 1 64) (y expr))` is the same with x following the range.
+    fn eval_curve(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        let plot = matches!(&expr[0], TvkObject::Atom("plot-2d"));
+        let name = if plot { "plot-2d" } else { "curve" };
+        let (ranges, coords) = self.eval_sampling_args(name, &expr[1..], pipeline)?;
+        let t = match ranges[..] {
+            [t] => t,
+            _ => {
+                self.errors.push(format!("{}: expected one range", name));
+                return None;
+            },
+        };
+        let points = self.with_ranges(&ranges, |interpreter| {
+            let mut points = Vec::with_capacity(t.steps as usize + 1);
+            for i in 0..=t.steps {
+                let value = t.value(i);
+                let mut point = interpreter.eval_sample(&ranges, &[value], &coords, pipeline)?;
+                if plot && coords[0].is_none() {
+                    point[0] = value;
+                }
+                points.push(point);
+            }
+            Some(points)
+        });
+        match points {
+            Some(points) => Some(InnerType::Mesh(vec![shapes::polyline(name, &points)])),
+            None => {
+                self.errors.push(format!("{}: coordinates must evaluate to numbers", name));
+                None
+            },
+        }
+    }
+
+    fn eval_load_gltf(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        let path = Path::new(Self::eval_path(expr.get(1)?)?);
+        let primitives = match gltf::load_gltf(path) {
+            Ok(primitives) => primitives,
+            Err(e) => {
+                self.errors.push(format!("load-gltf: {}", e));
+                return None;
+            },
+        };
+        // Embedded images are keyed by the scene file, they are reused as long
+        // as it doesn't change.
+        let file = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let modified = std::fs::metadata(&file)
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let mesh = primitives
+            .into_iter()
+            .map(|primitive| MeshGroup {
+                name: primitive.name,
+                vertices: primitive.vertices,
+                indices: primitive.indices,
+                material: Some(Self::eval_gltf_material(primitive.material, &file, modified, pipeline)),
+                id: MeshId::new(),
+            })
+            .collect();
+        Some(InnerType::Mesh(mesh))
+    }
+
+    fn eval_gltf_material(
+        gltf_material: gltf::GltfMaterial,
+        file: &Path,
+        modified: SystemTime,
+        pipeline: &mut MSAAPipeline
+        ) -> Material {
+        let mut upload = |image: gltf::GltfImage, color_space| {
+            let key = PathBuf::from(format!("{}#image{}", file.display(), image.index));
+            pipeline.embedded_texture(key, modified, image.width, image.height, image.data, color_space)
+        };
+        let mut material = Material {
+            tint: gltf_material.base_color,
+            blend: if gltf_material.blend {
+                BlendMode::Alpha
+            } else {
+                BlendMode::Opaque
+            },
+            ..Default::default()
+        };
+        if let Some(image) = gltf_material.base_color_texture {
+            material.base_color = upload(image, ColorSpace::Srgb);
+        }
+        if let Some(image) = gltf_material.normal_texture {
+            material.normal_map = Some(upload(image, ColorSpace::Linear));
+        }
+        material
+    }
+
+    fn load_texture(
+        &mut self,
+        path: &Path,
+        color_space: ColorSpace,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<Arc<Texture>> {
+        match pipeline.load_texture(&path.to_string_lossy(), color_space) {
+            Ok(texture) => Some(texture),
+            Err(e) => {
+                self.errors.push(format!("texture: {}", e));
+                None
+            },
+        }
+    }
+
+    fn eval_path(expr: &TvkObject<'a>) -> Option<&'a str> {
+        match expr {
+            TvkObject::Atom(path) | TvkObject::StringLiteral(path) => Some(*path),
+            _ => None,
+        }
+    }
+
+    /// Names models after the binding they were defined with. Models split
+    /// from a mesh keep their group name as a suffix.
+    fn name_models(val: &mut InnerType, key: &str) {
+        match val {
+            InnerType::Model(model) => {
+                model.name = if model.name.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", key, model.name)
+                };
+            },
+            InnerType::List(vals) => {
+                for val in vals {
+                    Self::name_models(val, key);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    fn eval_export(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) {
+        let (model, path) = match (expr.get(1), expr.get(2).and_then(Self::eval_path)) {
+            (Some(model), Some(path)) => (model, path),
+            _ => return,
+        };
+        let mut models = Vec::new();
+        match self.eval(model, pipeline) {
+            Some(val) => Self::collect_models(val, &mut models),
+            None => return,
+        }
+        let format = match &expr[0] {
+            TvkObject::Atom("export-obj") => ExportFormat::Obj,
+            _ => ExportFormat::Ply,
+        };
+        self.exports.push(Export {
+            format,
+            models,
+            path: PathBuf::from(path),
+        });
+    }
+
+    fn collect_models(val: InnerType, models: &mut Vec<Model>) {
+        match val {
+            InnerType::Model(model) => models.push(model),
+            InnerType::Node(node) => node.flatten(identity_mat4(), models),
+            InnerType::List(vals) => {
+                for val in vals {
+                    Self::collect_models(val, models);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    fn eval_draw(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) {
+        for drawable in &expr[1..] {
+            match self.eval(drawable, pipeline) {
+                Some(val) => Self::submit_drawable(val, pipeline),
+                None => {
+                    if let TvkObject::List(drawables) = drawable {
+                        for drawable in drawables {
+                            if let Some(val) = self.eval(drawable, pipeline) {
+                                Self::submit_drawable(val, pipeline);
                             }
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    /// `(animate target (keys (time value)...) (ease linear|cubic|step)
+    /// (loop once|repeat|ping-pong))` binds `target` to the keys interpolated
+    /// at the current `time`. Easing defaults to linear and looping to once.
+    fn eval_animate(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) {
+        let target = match expr.get(1) {
+            Some(TvkObject::Atom(target)) => *target,
+            _ => {
+                self.errors.push("animate: expected a target name".to_string());
+                return;
+            },
+        };
+        let mut track = Track {
+            keys: Vec::new(),
+            ease: Ease::Linear,
+            looping: Loop::Once,
+        };
+        for option in &expr[2..] {
+            let option: &[TvkObject<'a>] = match option {
+                TvkObject::List(option) => option,
+                _ => &[],
+            };
+            match option {
+                [TvkObject::Atom("keys"), keys @ ..] => {
+                    for key in keys {
+                        let value = match key {
+                            TvkObject::List(key) if key.len() == 2 => self
+                                .eval_number(&key[0], pipeline)
+                                .zip(self.eval(&key[1], pipeline)),
+                            _ => None,
+                        };
+                        match value {
+                            Some(value) => track.keys.push(value),
+                            None => self.errors.push(format!("animate {}: keys are (time value)", target)),
+                        }
+                    }
+                },
+                [TvkObject::Atom("ease"), TvkObject::Atom(ease)] => {
+                    track.ease = match *ease {
+                        "linear" => Ease::Linear,
+                        "cubic" => Ease::Cubic,
+                        "step" => Ease::Step,
+                        _ => {
+                            self.errors.push(format!("animate {}: unknown ease {}", target, ease));
+                            Ease::Linear
+                        },
+                    };
+                },
+                [TvkObject::Atom("loop"), TvkObject::Atom(looping)] => {
+                    track.looping = match *looping {
+                        "once" => Loop::Once,
+                        "repeat" => Loop::Repeat,
+                        "ping-pong" => Loop::PingPong,
+                        _ => {
+                            self.errors.push(format!("animate {}: unknown loop {}", target, looping));
+                            Loop::Once
+                        },
+                    };
+                },
+                _ => self.errors.push(format!("animate {}: expected keys, ease or loop", target)),
+            }
+        }
+        track.keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.animation_end = self.animation_end.max(track.end());
+        let time = match self.bindings.get("time") {
+            Some(InnerType::Float(time)) => *time,
+            _ => 0.0,
+        };
+        match track.sample(time) {
+            Some(value) => {
+                self.bindings.insert(target.to_string(), value);
+            },
+            None => self.errors.push(format!("animate {}: no keys", target)),
+        }
+    }
+
+    /// `(node name (transform ...) children...)`, where children are models,
+    /// lists of them or other nodes.
+    fn eval_node(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) -> Option<InnerType> {
+        let name = match expr.get(1)? {
+            TvkObject::Atom(name) | TvkObject::StringLiteral(name) => name.to_string(),
+            _ => return None,
+        };
+        let transform = match self.eval(expr.get(2)?, pipeline)? {
+            InnerType::Transform(transform) => transform,
+            _ => return None,
+        };
+        let mut children = Vec::new();
+        for child in &expr[3..] {
+            match self.eval(child, pipeline) {
+                Some(child) => {
+                    if !Self::collect_node_children(child, &mut children) {
+                        self.errors.push(format!("node {}: children must be models or nodes", name));
+                    }
+                },
+                None => self.errors.push(format!("node {}: invalid child", name)),
+            }
+        }
+        Some(InnerType::Node(Node {
+            name,
+            transform,
+            children,
+        }))
+    }
+
+    fn collect_node_children(val: InnerType, children: &mut Vec<NodeChild>) -> bool {
+        match val {
+            InnerType::Model(model) => children.push(NodeChild::Model(Box::new(model))),
+            InnerType::Node(node) => children.push(NodeChild::Node(node)),
+            InnerType::List(vals) => {
+                // Every child is collected even after a bad one.
+                let mut ok = true;
+                for val in vals {
+                    ok &= Self::collect_node_children(val, children);
+                }
+                return ok;
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    fn eval_use_camera(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) {
+        match expr.get(1).and_then(|camera| self.eval(camera, pipeline)) {
+            Some(InnerType::Camera(camera)) => pipeline.use_camera(camera),
+            _ => self.errors.push("use-camera: expected a camera".to_string()),
+        }
+    }
+
+    /// `(viewport (rect x y w h) (camera cam))`, with the rectangle given in
+    /// fractions of the 3D area. Without a camera the active one is used.
+    fn eval_viewport(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) {
+        let rect = match expr.get(1) {
+            Some(TvkObject::List(rect)) if rect.len() == 5 && matches!(rect[0], TvkObject::Atom("rect")) => {
+                let mut values = [0.0; 4];
+                for (value, e) in values.iter_mut().zip(&rect[1..]) {
+                    match self.eval_number(e, pipeline) {
+                        Some(n) => *value = n,
+                        None => {
+                            self.errors.push("viewport: rect values must evaluate to numbers".to_string());
+                            return;
+                        },
                     }
                 }
+                values
+            },
+            _ => {
+                self.errors.push("viewport: expected (rect x y w h)".to_string());
+                return;
+            },
+        };
+        let camera = match expr.get(2).map(|camera| self.eval(camera, pipeline)) {
+            None => None,
+            Some(Some(InnerType::Camera(camera))) => Some(camera),
+            Some(_) => {
+                self.errors.push("viewport: expected a camera".to_string());
+                return;
+            },
+        };
+        pipeline.add_viewport(Viewport { rect, camera });
+    }
+
+    fn submit_drawable(drawable: InnerType, pipeline: &mut MSAAPipeline) {
+        match drawable {
+            InnerType::Model(m) => pipeline.receive_model(m),
+            InnerType::VertexBuffer(vb) => pipeline.receive_vertex_buffer(vb),
+            InnerType::Light(light) => pipeline.receive_light(light),
+            InnerType::Node(node) => {
+                let mut models = Vec::new();
+                node.flatten(identity_mat4(), &mut models);
+                for model in models {
+                    pipeline.receive_model(model);
+                }
+            },
+            // Meshes drawn directly use the scene camera and default transform.
+            InnerType::Mesh(mesh) => {
+                for group in mesh {
+                    pipeline.receive_model(Model {
+                        name: group.name,
+                        vertices: group.vertices,
+                        indices: group.indices,
+                        material: group.material.unwrap_or_default(),
+                        mesh: group.id,
+                        ..Default::default()
+                    });
+                }
+            },
+            InnerType::List(drawables) => {
+                for drawable in drawables {
+                    Self::submit_drawable(drawable, pipeline);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    fn eval_draw_instanced(
+        &mut self,
+        expr: &Vec<TvkObject<'a>>,
+        pipeline: &mut MSAAPipeline
+        ) {
+        if expr.len() != 4 {
+            self.errors.push("draw-instanced: expected (draw-instanced model count transforms)".to_string());
+            return;
+        }
+        let mut model = match self.eval(&expr[1], pipeline) {
+            Some(InnerType::Model(model)) => model,
+            _ => {
+                self.errors.push("draw-instanced: expected a model".to_string());
+                return;
             }
+        };
+        let count = match self.eval(&expr[2], pipeline) {
+            Some(InnerType::UInt(count)) => count,
+            Some(InnerType::Float(count)) if count >= 0.0 && count.fract() == 0.0 => count as u32,
+            _ => {
+                self.errors.push("draw-instanced: the count must be a non-negative integer".to_string());
+                return;
+            }
+        };
+        let transforms = self.eval_transform_list(&expr[3], pipeline);
+        if transforms.is_empty() {
+            self.errors.push("draw-instanced: expected at least one transform".to_string());
+            return;
+        }
+        model.instances = (0..count as usize)
+            .map(|i| InstanceData::from(transforms[i % transforms.len()].to_mat4()))
+            .collect();
+        pipeline.receive_model(model);
+    }
+
+    fn eval_transform_list(
+        &mut self,
+        expr: &TvkObject<'a>,
+        pipeline: &mut MSAAPipeline
+        ) -> Vec<Transform> {
+        match self.eval(expr, pipeline) {
+            Some(InnerType::Transform(t)) => vec![t],
+            Some(InnerType::List(items)) => items
+                .into_iter()
+                .filter_map(|item| match item {
+                    InnerType::Transform(t) => Some(t),
+                    _ => None,
+                })
+                .collect(),
+            None => {
+                let mut transforms = Vec::new();
+                if let TvkObject::List(items) = expr {
+                    for item in items {
+                        if let Some(InnerType::Transform(t)) = self.eval(item, pipeline) {
+                            transforms.push(t);
+                        }
+                    }
+                }
+                transforms
+            },
+            _ => Vec::new(),
         }
-        return None;
     }
 
     fn eval_identifier(
         &self,
         expr: &TvkObject<'a>) -> Option<InnerType> {
         if let TvkObject::Atom(identifier) = expr {
-            return self.bindings.get(identifier).cloned();
+            return self.bindings.get(*identifier).cloned();
         } else {
             return None;
         }
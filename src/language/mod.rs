@@ -1,3 +1,5 @@
+pub mod animation;
 pub mod interpreter;
 pub mod parser;
+pub mod program;
 pub mod types;
@@ -18,6 +18,10 @@ use std::str::{self, from_utf8};
 pub struct Parser<'a> {
     source: &'a str,
     pub exprs: Vec<TvkObject<'a>>,
+    /// Byte offset in the source where each of `exprs` starts.
+    pub starts: Vec<usize>,
+    /// Byte offset in the source right after each of `exprs`.
+    pub ends: Vec<usize>,
 }
 
 impl<'a> Parser<'a> {
@@ -25,16 +29,21 @@ impl<'a> Parser<'a> {
         Self {
             source: src,
             exprs: Vec::new(),
+            starts: Vec::new(),
+            ends: Vec::new(),
         }
     }
 
-    pub fn parse(&'a mut self) -> Vec<TvkObject> {
+    pub fn parse(&mut self) -> Vec<TvkObject<'a>> {
+        let len = self.source.len();
         while self.source.len() > 0 {
             let r = Self::parse_tvk(self.source);
             match r {
                 Ok((src, expr)) => {
+                    self.starts.push(len - Self::consume_space(self.source).len());
                     self.source = src;
                     self.exprs.push(expr);
+                    self.ends.push(len - src.len());
                 }
                 Err(nom::Err::Incomplete(_)) => {
                     if self.source.len() > 0 {
@@ -54,6 +63,7 @@ impl<'a> Parser<'a> {
         alt((
             delimited(char('('), Self::parse_list, char(')')),
             Self::parse_color,
+            Self::parse_string_literal,
             Self::parse_uint_literal,
             Self::parse_float_literal,
             Self::parse_atom,
@@ -71,7 +81,8 @@ impl<'a> Parser<'a> {
 
     fn is_atom_char(c: u8) -> bool {
         is_alphanumeric(c) || c == b'-' || c == b'_' ||
-            c == b'/' || c == b'\\' || c == b'.'
+            c == b'/' || c == b'\\' || c == b'.' ||
+            c == b'+' || c == b'*'
     }
 
     fn parse_atom(src: &'a str) -> IResult<&'a str, TvkObject<'a>> {
@@ -87,6 +98,13 @@ impl<'a> Parser<'a> {
         }))
     }
 
+    fn parse_string_literal(src: &'a str) -> IResult<&'a str, TvkObject<'a>> {
+        let src = Self::consume_space(src);
+        let (src, string) =
+            delimited(char('"'), take_while(|c: char| c != '"'), char('"'))(src)?;
+        Ok((src, StringLiteral(string)))
+    }
+
     fn parse_float_literal(src: &'a str) -> IResult<&'a str, TvkObject<'a>> {
         let src = Self::consume_space(src);
         if let Ok((src, f)) = float::<&str, ()>(src) {
@@ -0,0 +1,289 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::assets::export::Export;
+use crate::language::interpreter::Interpreter;
+use crate::language::parser::Parser;
+use crate::language::types::{InnerType, InterpretingMode, TvkObject};
+use crate::rendering_pipeline::MSAAPipeline;
+
+/// Bindings updated by the clock before every evaluation.
+const CLOCK_BINDINGS: [&str; 2] = ["time", "frame"];
+
+/// Script kept between frames, so the top level forms that depend on `time`
+/// or `frame` can be evaluated again on their own every frame.
+pub struct Program {
+    /// Top level forms evaluated again every frame, in the order they were
+    /// written, see `classify`.
+    animated: Vec<Form>,
+    bindings: HashMap<String, InnerType>,
+    /// Errors already in the console since the last `load`, so a failing
+    /// animated form is only reported once.
+    reported: HashSet<String>,
+    /// Seconds since the program started, it keeps going when the code is
+    /// evaluated again.
+    pub time: f32,
+    pub frame: u32,
+    /// The clock only moves while playing, `time` can still be scrubbed.
+    pub playing: bool,
+    /// Time of the last key of the `animate` forms.
+    pub duration: f32,
+    /// Exports asked for by the last `load`, left for the caller to write.
+    pub exports: Vec<Export>,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Self {
+            animated: Vec::new(),
+            bindings: HashMap::new(),
+            reported: HashSet::new(),
+            time: 0.0,
+            frame: 0,
+            playing: true,
+            duration: 0.0,
+            exports: Vec::new(),
+        }
+    }
+
+    /// Evaluates the whole of `code` in order. What the static forms leave in
+    /// the scene is kept, the animated ones are kept to be evaluated again
+    /// every frame.
+    pub fn load(&mut self, code: &str, pipeline: &mut MSAAPipeline) -> (InterpretingMode, Vec<String>) {
+        pipeline.clear_scene();
+        let mut parser = Parser::new(code);
+        let exprs = parser.parse();
+        let animated = classify(&exprs);
+        let first_scene = (0..exprs.len()).find(|&i| animated[i] && !binds(&exprs[i]));
+        let mut interpreter = Interpreter::new();
+        self.bind_clock(&mut interpreter);
+        for (i, e) in exprs.iter().enumerate() {
+            if Some(i) == first_scene {
+                pipeline.mark_static();
+            }
+            interpreter.eval(e, pipeline);
+        }
+        self.animated = exprs
+            .iter()
+            .zip(animated)
+            .filter(|(_, animated)| *animated)
+            .map(|(e, _)| Form::new(e))
+            .collect();
+        self.bindings = interpreter.bindings;
+        self.duration = interpreter.animation_end;
+        self.exports = interpreter.exports;
+        self.reported = interpreter.errors.iter().cloned().collect();
+        (interpreter.interpreting_mode, interpreter.errors)
+    }
+
+    /// Moves the clock `dt` seconds and a frame forward if it's playing and
+    /// evaluates the animated forms again. Returns the errors that weren't
+    /// reported yet.
+    pub fn tick(&mut self, dt: f32, pipeline: &mut MSAAPipeline) -> Vec<String> {
+        if self.playing {
+            self.time += dt;
+            self.frame = self.frame.wrapping_add(1);
+        }
+        if self.animated.is_empty() {
+            return Vec::new();
+        }
+        pipeline.restore_static();
+        let mut interpreter = Interpreter::new();
+        interpreter.bindings = std::mem::take(&mut self.bindings);
+        self.bind_clock(&mut interpreter);
+        for form in &self.animated {
+            interpreter.eval(&form.object(), pipeline);
+        }
+        self.bindings = interpreter.bindings;
+        self.duration = interpreter.animation_end;
+        let reported = &mut self.reported;
+        interpreter
+            .errors
+            .into_iter()
+            .filter(|e| reported.insert(e.clone()))
+            .collect()
+    }
+
+    fn bind_clock(&self, interpreter: &mut Interpreter) {
+        interpreter
+            .bindings
+            .insert(CLOCK_BINDINGS[0].to_string(), InnerType::Float(self.time));
+        interpreter
+            .bindings
+            .insert(CLOCK_BINDINGS[1].to_string(), InnerType::UInt(self.frame));
+    }
+}
+
+/// A parsed form owning its atoms and strings, so it can outlive the code it
+/// was parsed from.
+enum Form {
+    Float(f32),
+    Atom(String),
+    UInt(u32),
+    String(String),
+    Color([f32; 4]),
+    List(Vec<Form>),
+}
+
+impl Form {
+    fn new(expr: &TvkObject) -> Self {
+        match expr {
+            TvkObject::FloatLiteral(f) => Form::Float(*f),
+            TvkObject::Atom(atom) => Form::Atom(atom.to_string()),
+            TvkObject::UIntLiteral(n) => Form::UInt(*n),
+            TvkObject::StringLiteral(s) => Form::String(s.to_string()),
+            TvkObject::Color(c) => Form::Color(*c),
+            TvkObject::List(l) => Form::List(l.iter().map(Form::new).collect()),
+        }
+    }
+
+    fn object(&self) -> TvkObject<'_> {
+        match self {
+            Form::Float(f) => TvkObject::FloatLiteral(*f),
+            Form::Atom(atom) => TvkObject::Atom(atom),
+            Form::UInt(n) => TvkObject::UIntLiteral(*n),
+            Form::String(s) => TvkObject::StringLiteral(s),
+            Form::Color(c) => TvkObject::Color(*c),
+            Form::List(l) => TvkObject::List(l.iter().map(Form::object).collect()),
+        }
+    }
+}
+
+/// Tells which top level forms are evaluated again every frame: `animate`
+/// forms and forms mentioning the clock or a name bound by an animated form.
+/// Rebinding a name an animated form binds or reads makes a form animated too,
+/// or the frames would see a different value than `load`. Once a form adding
+/// to the scene is animated, the ones after it are too, so the scene keeps the
+/// order they were written in. Exports are never animated, they would write
+/// their file every frame.
+fn classify(exprs: &[TvkObject]) -> Vec<bool> {
+    let mut names: HashSet<&str> = CLOCK_BINDINGS.into_iter().collect();
+    let mut read: HashSet<&str> = HashSet::new();
+    let mut scene_animated = false;
+    exprs
+        .iter()
+        .map(|expr| {
+            if is_export(expr) {
+                return false;
+            }
+            let bound = binding(expr);
+            let animated = head(expr) == Some("animate")
+                || mentions(expr, &names)
+                || bound.map_or(scene_animated, |name| names.contains(name) || read.contains(name));
+            if animated {
+                names.extend(bound);
+                atoms(expr, &mut read);
+                scene_animated |= !binds(expr);
+            }
+            animated
+        })
+        .collect()
+}
+
+fn head<'a>(expr: &TvkObject<'a>) -> Option<&'a str> {
+    match expr {
+        TvkObject::List(l) => match l.first() {
+            Some(TvkObject::Atom(head)) => Some(*head),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_export(expr: &TvkObject) -> bool {
+    matches!(head(expr), Some("export-obj" | "export-ply"))
+}
+
+/// `def` and `animate` only bind a name, they add nothing to the scene.
+fn binds(expr: &TvkObject) -> bool {
+    matches!(head(expr), Some("def" | "animate"))
+}
+
+/// Name bound by a `def` or `animate` form.
+fn binding<'a>(expr: &TvkObject<'a>) -> Option<&'a str> {
+    match expr {
+        TvkObject::List(l) if binds(expr) => match l.get(1) {
+            Some(TvkObject::Atom(name)) => Some(*name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn mentions(expr: &TvkObject, names: &HashSet<&str>) -> bool {
+    match expr {
+        TvkObject::Atom(atom) => names.contains(atom),
+        TvkObject::List(l) => l.iter().any(|e| mentions(e, names)),
+        _ => false,
+    }
+}
+
+fn atoms<'a>(expr: &TvkObject<'a>, into: &mut HashSet<&'a str>) {
+    match expr {
+        TvkObject::Atom(atom) => {
+            into.insert(*atom);
+        }
+        TvkObject::List(l) => l.iter().for_each(|e| atoms(e, into)),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animated(code: &str) -> Vec<bool> {
+        let mut parser = Parser::new(code);
+        classify(&parser.parse())
+    }
+
+    #[test]
+    fn static_forms_after_the_clock_stay_static() {
+        let code = "(def a (+ time 1.0)) (def m (load-obj \"m.obj\")) (def b (* 2.0 a)) (def c (sin b))";
+        assert_eq!(animated(code), [true, false, true, true]);
+    }
+
+    #[test]
+    fn animate_targets_are_animated() {
+        let code = "(animate spin (keys (0.0 0.0) (1.0 6.28))) (def r (rotate spin)) (def s (scale 2.0))";
+        assert_eq!(animated(code), [true, true, false]);
+    }
+
+    #[test]
+    fn scene_forms_keep_their_order() {
+        let code = "(draw m) (draw-instanced m 2 (list a b)) (use-camera cam) (draw (+ time 1.0)) (use-camera cam) (def d 1.0)";
+        assert_eq!(animated(code), [false, false, false, true, true, false]);
+    }
+
+    #[test]
+    fn rebinding_animated_names_is_animated() {
+        let code = "(def a 1.0) (def b (+ a time)) (def a 2.0) (def b 3.0) (def c b)";
+        assert_eq!(animated(code), [false, true, true, true, true]);
+    }
+
+    #[test]
+    fn exports_are_never_animated() {
+        assert_eq!(animated("(export-obj \"out.obj\" (+ time 1.0))"), [false]);
+    }
+
+    #[test]
+    fn forms_survive_their_source() {
+        let form = {
+            let code = String::from("(def a (list 1 2.5 \"s\" #FF0000FF))");
+            let mut parser = Parser::new(&code);
+            Form::new(&parser.parse()[0])
+        };
+        let object = form.object();
+        let list = match &object {
+            TvkObject::List(l) => l,
+            _ => panic!("{:?}", object),
+        };
+        assert!(matches!(list[..2], [TvkObject::Atom("def"), TvkObject::Atom("a")]));
+        assert!(matches!(
+            &list[2],
+            TvkObject::List(l) if matches!(
+                l[..],
+                [TvkObject::Atom("list"), TvkObject::UIntLiteral(1), TvkObject::FloatLiteral(_), TvkObject::StringLiteral("s"), TvkObject::Color(_)]
+            )
+        ));
+    }
+}
@@ -1,11 +1,27 @@
-use std::sync::Arc;
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use bytemuck::{Pod, Zeroable};
-use vulkano::image::{ImageDimensions, ImmutableImage, view::ImageView};
+use vulkano::{
+    buffer::{DeviceLocalBuffer, TypedBufferAccess},
+    format::Format,
+    image::{view::ImageView, ImageDimensions, ImmutableImage},
+    sampler::Sampler,
+};
 
 use crate::tvk_glm::{
-    identity_mat4, look_at_rh, mult_mat4, perspective_rh_no, rotate_mat4, scale_mat4,
-    translate_mat4,
+    cross_vec3, identity_mat4,
+    intersect::{ray_aabb, ray_triangle, Ray},
+    len_vec3, look_at_rh,
+    matrix::Mat4,
+    mult_mat4, normalize_vec3, rotate_mat4, scale_mat4, translate_mat4,
+    vec3_subs, vector,
 };
 
 type Vec3 = [f32; 3];
@@ -26,20 +42,109 @@ vulkano::impl_vertex!(Vertex, position, color);
 #[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
 pub struct TextureVertex {
     pub position: Position,
-    pub uv: [f32; 2]
+    pub uv: [f32; 2],
+    pub normal: Vec3,
+}
+vulkano::impl_vertex!(TextureVertex, position, uv, normal);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct InstanceData {
+    pub instance_c0: Vec4,
+    pub instance_c1: Vec4,
+    pub instance_c2: Vec4,
+    pub instance_c3: Vec4,
+}
+vulkano::impl_vertex!(InstanceData, instance_c0, instance_c1, instance_c2, instance_c3);
+
+impl Default for InstanceData {
+    fn default() -> Self {
+        Self::from(identity_mat4())
+    }
+}
+
+impl From<[[f32; 4]; 4]> for InstanceData {
+    fn from(mat: [[f32; 4]; 4]) -> Self {
+        Self {
+            instance_c0: mat[0],
+            instance_c1: mat[1],
+            instance_c2: mat[2],
+            instance_c3: mat[3],
+        }
+    }
+}
+
+impl From<InstanceData> for [[f32; 4]; 4] {
+    fn from(instance: InstanceData) -> Self {
+        [
+            instance.instance_c0,
+            instance.instance_c1,
+            instance.instance_c2,
+            instance.instance_c3,
+        ]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InterpretingMode {
+    Continuous,
+    Manual,
 }
-vulkano::impl_vertex!(TextureVertex, position, uv);
 
 type Angle = f32;
 type Center = Vec3;
 type Up = Vec3;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective {
+        fovy: f32,
+        z_near: f32,
+        z_far: f32,
+    },
+    Orthographic {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        z_near: f32,
+        z_far: f32,
+    },
+}
+
+impl Projection {
+    /// Projection matrix for Vulkan: depth goes from 0 at the near plane to 1
+    /// at the far one, and clip space Y is flipped so +Y still points up on
+    /// screen.
+    pub fn to_mat4(self, aspect_ratio: f32) -> [[f32; 4]; 4] {
+        let mut projection = match self {
+            Projection::Perspective {
+                fovy,
+                z_near,
+                z_far,
+            } => Mat4::perspective_rh_zo(fovy, aspect_ratio, z_near, z_far),
+            Projection::Orthographic {
+                left,
+                right,
+                bottom,
+                top,
+                z_near,
+                z_far,
+            } => Mat4::orthographic_rh_zo(left, right, bottom, top, z_near, z_far),
+        };
+        for column in &mut projection.cols {
+            column.y = -column.y;
+        }
+        projection.into()
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Camera {
     pub position: Position,
     pub center: Center,
     pub up: Up,
-    pub perspective: [f32; 3],
+    pub projection: Projection,
 }
 
 impl Default for Camera {
@@ -48,24 +153,95 @@ impl Default for Camera {
             position: [2.0, 2.0, 2.0],
             center: [0.0, 0.0, 0.0],
             up: [0.0, 1.0, 0.0],
-            perspective: [0.75, 0.1, 10.0],
+            projection: Projection::Perspective {
+                fovy: 0.75,
+                z_near: 0.1,
+                z_far: 10.0,
+            },
         }
     }
 }
 
+/// Single step of a `transform`.
 #[derive(Clone, Debug)]
+pub enum TransformOp {
+    Translate(Vec3),
+    Scale(Vec3),
+    /// Angle in radians around an axis.
+    Rotate(Angle, Vec3),
+    Matrix([[f32; 4]; 4]),
+}
+
+impl TransformOp {
+    pub fn to_mat4(&self) -> [[f32; 4]; 4] {
+        match self {
+            TransformOp::Translate(v) => translate_mat4(identity_mat4(), *v),
+            TransformOp::Scale(v) => scale_mat4(identity_mat4(), *v),
+            TransformOp::Rotate(angle, axis) => rotate_mat4(identity_mat4(), *angle, *axis),
+            TransformOp::Matrix(m) => *m,
+        }
+    }
+}
+
+/// Steps applied to a model in the order they were written, so
+/// `(transform (rotate ..) (translate ..))` spins the model in place and then
+/// moves it. No steps leave it as it is.
+#[derive(Clone, Debug, Default)]
 pub struct Transform {
-    pub translate: Vec3,
-    pub scale: Vec3,
-    pub rotate: (Angle, Vec3),
+    pub ops: Vec<TransformOp>,
 }
 
-impl Default for Transform {
-    fn default() -> Self {
-        Self {
-            translate: [0.0, 0.0, 0.0],
-            scale: [1.0, 1.0, 1.0],
-            rotate: (0.0, [0.0, 1.0, 0.0]),
+impl Transform {
+    pub fn to_mat4(&self) -> [[f32; 4]; 4] {
+        self.ops
+            .iter()
+            .fold(identity_mat4(), |matrix, op| mult_mat4(matrix, op.to_mat4()))
+    }
+}
+
+/// Scene graph element built with `node`. Its children are placed inside its
+/// transform, so moving a node moves everything under it.
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    pub transform: Transform,
+    pub children: Vec<NodeChild>,
+}
+
+#[derive(Clone, Debug)]
+pub enum NodeChild {
+    Node(Node),
+    Model(Box<Model>),
+}
+
+impl Node {
+    /// Models under this node, named after the path of nodes leading to them
+    /// and with their `parent` set to the world matrix of the node holding
+    /// them. `parent` is the world matrix of this node's parent.
+    pub fn flatten(&self, parent: [[f32; 4]; 4], models: &mut Vec<Model>) {
+        let world = mult_mat4(self.transform.to_mat4(), parent);
+        for child in &self.children {
+            match child {
+                NodeChild::Node(node) => {
+                    let first = models.len();
+                    node.flatten(world, models);
+                    for model in &mut models[first..] {
+                        model.name = format!("{}.{}", self.name, model.name);
+                    }
+                },
+                NodeChild::Model(model) => {
+                    let name = if model.name.is_empty() {
+                        self.name.clone()
+                    } else {
+                        format!("{}.{}", self.name, model.name)
+                    };
+                    models.push(Model {
+                        name,
+                        parent: world,
+                        ..model.as_ref().clone()
+                    });
+                },
+            }
         }
     }
 }
@@ -74,52 +250,471 @@ type VertexBuffer = Vec<Vertex>;
 type TexVertexBuffer = Vec<TextureVertex>;
 type IndexBuffer = Vec<u32>;
 
+/// Identifies the geometry of a mesh group for as long as it lives, copies
+/// included, so the models made from it every frame can share its buffers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshId {
+    source: u64,
+    /// Normals generated after the geometry was created.
+    normals: Option<NormalMode>,
+}
+
+impl MeshId {
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self {
+            source: NEXT.fetch_add(1, Ordering::Relaxed),
+            normals: None,
+        }
+    }
+}
+
+impl Default for MeshId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Part of a mesh drawn with one material. Meshes coming from files usually
+/// have several of these, one per group or material change.
+#[derive(Clone, Debug)]
+pub struct MeshGroup {
+    pub name: String,
+    pub vertices: TexVertexBuffer,
+    pub indices: IndexBuffer,
+    pub material: Option<Material>,
+    pub id: MeshId,
+}
+
+pub type Mesh = Vec<MeshGroup>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SamplerFilter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SamplerWrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+/// Sampling state referenced by models. Equal configurations share a single
+/// Vulkan sampler, see `MSAAPipeline::sampler`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerConfig {
+    pub filter: SamplerFilter,
+    pub wrap: SamplerWrap,
+    pub anisotropy: u32,
+    pub mipmaps: bool,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            filter: SamplerFilter::Linear,
+            wrap: SamplerWrap::Repeat,
+            anisotropy: 1,
+            mipmaps: true,
+        }
+    }
+}
+
+/// Decoded texture as loaded from disk. Shared between every model that uses
+/// the same file, see `MSAAPipeline::load_texture`.
+#[derive(Debug)]
+pub struct Texture {
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+    pub dimensions: ImageDimensions,
+    pub color_space: ColorSpace,
+    pub format: Format,
+}
+
+/// A single white texel, so materials without a texture just show their tint.
+impl Default for Texture {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            data: vec![255; 4],
+            dimensions: ImageDimensions::Dim2d { width: 1, height: 1, array_layers: 1 },
+            color_space: ColorSpace::Srgb,
+            format: Format::R8G8B8A8_SRGB,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Opaque,
+    Alpha,
+    Additive,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct MaterialUniform {
+    pub tint: Color,
+    pub shininess: f32,
+    pub has_normal_map: u32,
+    pub _pad: [u32; 2],
+}
+
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub base_color: Arc<Texture>,
+    pub normal_map: Option<Arc<Texture>>,
+    pub tint: Color,
+    pub shininess: f32,
+    pub blend: BlendMode,
+    pub sampler: SamplerConfig,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color: Arc::new(Texture::default()),
+            normal_map: None,
+            tint: [1.0, 1.0, 1.0, 1.0],
+            shininess: 32.0,
+            blend: BlendMode::Opaque,
+            sampler: SamplerConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalMode {
+    Flat,
+    Smooth,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LightKind {
+    Ambient,
+    Directional { direction: Vec3 },
+    Point { position: Position },
+    Spot { position: Position, direction: Vec3, angle: Angle },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Color,
+    pub intensity: f32,
+}
+
+pub const MAX_LIGHTS: usize = 8;
+
+/// Matches the `Light` struct in `fs_tex`. The type of light is stored in
+/// `position.w` and the cosine of a spot light's cutoff in `direction.w`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct LightData {
+    pub position: Vec4,
+    pub direction: Vec4,
+    pub color: Vec4,
+}
+
+impl From<&Light> for LightData {
+    fn from(light: &Light) -> Self {
+        let color = [light.color[0], light.color[1], light.color[2], light.intensity];
+        let (position, direction) = match light.kind {
+            LightKind::Ambient => ([0.0, 0.0, 0.0, 0.0], [0.0; 4]),
+            LightKind::Directional { direction } => {
+                ([0.0, 0.0, 0.0, 1.0], [direction[0], direction[1], direction[2], 0.0])
+            }
+            LightKind::Point { position } => {
+                ([position[0], position[1], position[2], 2.0], [0.0; 4])
+            }
+            LightKind::Spot { position, direction, angle } => (
+                [position[0], position[1], position[2], 3.0],
+                [direction[0], direction[1], direction[2], angle.cos()],
+            ),
+        };
+        Self {
+            position,
+            direction,
+            color,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct LightsUniform {
+    pub count: u32,
+    pub _pad: [u32; 3],
+    pub lights: [LightData; MAX_LIGHTS],
+}
+
+/// GPU side copies of a model, uploaded once and reused every frame. The
+/// matrices, material and lights change every frame, so they are allocated
+/// when the model is drawn.
+#[derive(Clone)]
+pub struct ModelBuffers {
+    pub vertices: Arc<DeviceLocalBuffer<[TextureVertex]>>,
+    pub indices: Arc<DeviceLocalBuffer<[u32]>>,
+    pub instances: Arc<DeviceLocalBuffer<[InstanceData]>>,
+    pub texture: Arc<ImageView<ImmutableImage>>,
+    pub normal_map: Option<Arc<ImageView<ImmutableImage>>>,
+    pub sampler: Arc<Sampler>,
+}
+
+impl fmt::Debug for ModelBuffers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModelBuffers")
+            .field("vertices", &self.vertices.len())
+            .field("indices", &self.indices.len())
+            .field("instances", &self.instances.len())
+            .finish()
+    }
+}
+
+/// Part of the 3D area the scene is drawn to, with `rect` holding the
+/// `[x, y, width, height]` fractions of it measured from the top left corner.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    pub rect: [f32; 4],
+    /// Replaces the active camera inside this viewport.
+    pub camera: Option<Camera>,
+}
+
+impl Viewport {
+    pub const FULL: Self = Self {
+        rect: [0.0, 0.0, 1.0, 1.0],
+        camera: None,
+    };
+
+    /// `[x, y, width, height]` in pixels of an area of `dimensions` pixels,
+    /// clamped to it.
+    pub fn pixels(&self, dimensions: [u32; 2]) -> [u32; 4] {
+        let [width, height] = dimensions.map(|d| d as f32);
+        let x0 = (self.rect[0] * width).round().clamp(0.0, width) as u32;
+        let y0 = (self.rect[1] * height).round().clamp(0.0, height) as u32;
+        let x1 = ((self.rect[0] + self.rect[2]) * width).round().clamp(0.0, width) as u32;
+        let y1 = ((self.rect[1] + self.rect[3]) * height).round().clamp(0.0, height) as u32;
+        [x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0)]
+    }
+}
+
+/// State shared by every model drawn in a frame.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    /// Camera picked with `use-camera`.
+    pub camera: Camera,
+    /// Camera moved from the viewport. It takes precedence over the script
+    /// ones until it's reset.
+    pub view_camera: Option<Camera>,
+    /// Added with `viewport`, without any the whole area is used.
+    pub viewports: Vec<Viewport>,
+}
+
+impl Scene {
+    pub fn active_camera(&self) -> Camera {
+        self.view_camera.unwrap_or(self.camera)
+    }
+
+    pub fn camera_for(&self, model: &Model, viewport: &Viewport) -> Camera {
+        viewport
+            .camera
+            .or(self.view_camera)
+            .or(model.camera)
+            .unwrap_or(self.camera)
+    }
+
+    pub fn viewports(&self) -> &[Viewport] {
+        if self.viewports.is_empty() {
+            std::slice::from_ref(&Viewport::FULL)
+        } else {
+            &self.viewports
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Model {
+    pub name: String,
     pub vertices: Vec<TextureVertex>,
     pub indices: Vec<u32>,
     pub topology: String,
     pub transforms: Transform,
-    pub camera: Camera,
-    pub texture_data: (Vec<u8>, ImageDimensions),
-    pub texture: Option<Arc<ImageView<ImmutableImage>>>,
+    /// World matrix of the node the model hangs from, identity outside of
+    /// any `node`.
+    pub parent: [[f32; 4]; 4],
+    /// Overrides the scene camera for this model only.
+    pub camera: Option<Camera>,
+    pub material: Material,
+    pub instances: Vec<InstanceData>,
+    /// Geometry the vertices and indices come from.
+    pub mesh: MeshId,
+    pub buffers: Option<ModelBuffers>,
 }
 
 impl Default for Model {
     fn default() -> Self {
         Self {
+            name: String::new(),
             vertices: Vec::new(),
             indices: Vec::new(),
             topology: "RESERVED_TRIANGLE_LIST".to_string(),
             transforms: Transform::default(),
-            camera: Camera::default(),
-            texture_data: (Vec::new(), ImageDimensions::Dim1d { width: 0, array_layers: 0}),
-            texture: None,
+            parent: identity_mat4(),
+            camera: None,
+            material: Material::default(),
+            instances: Vec::new(),
+            mesh: MeshId::new(),
+            buffers: None,
         }
     }
 }
 
 impl Model {
-    pub fn generate_mvp_mats(&self, dimensions: [u32; 2]) -> [[[f32; 4]; 4]; 3] {
-        let translate = translate_mat4(identity_mat4(), self.transforms.translate);
-        let scale = scale_mat4(identity_mat4(), self.transforms.scale);
-        let rotate = rotate_mat4(
-            identity_mat4(),
-            self.transforms.rotate.0,
-            self.transforms.rotate.1,
-        );
-        let model = mult_mat4(translate, rotate);
-        let model = mult_mat4(model, scale);
-        let cam = self.camera;
+    /// Whether `other` comes from the same mesh and would upload the same
+    /// instance buffer and bind the same textures, so it can take over this
+    /// model's buffers.
+    pub fn shares_buffers_with(&self, other: &Model) -> bool {
+        let same_texture = |a: &Texture, b: &Texture| a.path == b.path && a.color_space == b.color_space;
+        self.mesh == other.mesh
+            && self.topology == other.topology
+            && bytemuck::cast_slice::<_, u8>(&self.instances) == bytemuck::cast_slice::<_, u8>(&other.instances)
+            && same_texture(&self.material.base_color, &other.material.base_color)
+            && match (&self.material.normal_map, &other.material.normal_map) {
+                (Some(a), Some(b)) => same_texture(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+            && self.material.sampler == other.material.sampler
+    }
+
+    /// Model matrix, its own transform applied inside the parent node's.
+    pub fn world_mat4(&self) -> [[f32; 4]; 4] {
+        mult_mat4(self.transforms.to_mat4(), self.parent)
+    }
+
+    /// Replaces the vertex normals with ones computed from the triangles in
+    /// `indices`. Flat normals need a vertex per triangle corner, so in that
+    /// mode the vertices are unshared and the indices rewritten.
+    pub fn generate_normals(&mut self, mode: NormalMode) {
+        if self.topology != "RESERVED_TRIANGLE_LIST" {
+            return;
+        }
+        self.mesh.normals = Some(mode);
+        let face_normal = |a: &TextureVertex, b: &TextureVertex, c: &TextureVertex| {
+            cross_vec3(vec3_subs(b.position, a.position), vec3_subs(c.position, a.position))
+        };
+        let safe_normalize = |n: Vec3| {
+            if len_vec3(n) > f32::EPSILON {
+                normalize_vec3(n)
+            } else {
+                [0.0, 0.0, 0.0]
+            }
+        };
+        match mode {
+            NormalMode::Smooth => {
+                let mut normals = vec![[0.0f32; 3]; self.vertices.len()];
+                for tri in self.indices.chunks_exact(3) {
+                    let (a, b, c) = (tri[0] as usize, tri[1] as usize, tri[2] as usize);
+                    if a >= normals.len() || b >= normals.len() || c >= normals.len() {
+                        continue;
+                    }
+                    // Unnormalized, so bigger faces weigh more.
+                    let n = face_normal(&self.vertices[a], &self.vertices[b], &self.vertices[c]);
+                    for i in [a, b, c] {
+                        for k in 0..3 {
+                            normals[i][k] += n[k];
+                        }
+                    }
+                }
+                for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+                    vertex.normal = safe_normalize(normal);
+                }
+            }
+            NormalMode::Flat => {
+                let mut vertices = Vec::with_capacity(self.indices.len());
+                for tri in self.indices.chunks_exact(3) {
+                    let corners: Option<Vec<TextureVertex>> = tri
+                        .iter()
+                        .map(|&i| self.vertices.get(i as usize).copied())
+                        .collect();
+                    if let Some(corners) = corners {
+                        let n = safe_normalize(face_normal(&corners[0], &corners[1], &corners[2]));
+                        for mut vertex in corners {
+                            vertex.normal = n;
+                            vertices.push(vertex);
+                        }
+                    }
+                }
+                self.indices = (0..vertices.len() as u32).collect();
+                self.vertices = vertices;
+            }
+        }
+    }
+
+    /// Closest hit of a world space ray with any instance of the model, as the
+    /// ray parameter. Triangle topologies are tested face by face, lines and
+    /// points only against their bounding box.
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        let positions: Vec<vector::Vec3> = self.vertices.iter().map(|v| v.position.into()).collect();
+        let first = *positions.first()?;
+        let (min, max) = positions
+            .iter()
+            .fold((first, first), |(min, max), p| (min.min(*p), max.max(*p)));
+        let triangle = |local: &Ray, face: &[u32]| {
+            let [a, b, c] = [face[0], face[1], face[2]].map(|i| positions.get(i as usize).copied());
+            ray_triangle(local, a?, b?, c?)
+        };
+        let model = Mat4::from(self.world_mat4());
+        let identity = [InstanceData::default()];
+        let instances = if self.instances.is_empty() {
+            &identity[..]
+        } else {
+            &self.instances[..]
+        };
+        instances
+            .iter()
+            .filter_map(|instance| {
+                let world = Mat4::from(<[[f32; 4]; 4]>::from(*instance)) * model;
+                // Transforming the ray instead of the mesh keeps its parameter.
+                let local = ray.transform(&world.inverse()?);
+                let bounds = ray_aabb(&local, min, max)?;
+                match self.topology.as_str() {
+                    "RESERVED_TRIANGLE_LIST" => self
+                        .indices
+                        .chunks_exact(3)
+                        .filter_map(|face| triangle(&local, face))
+                        .reduce(f32::min),
+                    "RESERVED_TRIANGLE_STRIP" => self
+                        .indices
+                        .windows(3)
+                        .filter_map(|face| triangle(&local, face))
+                        .reduce(f32::min),
+                    _ => Some(bounds),
+                }
+            })
+            .reduce(f32::min)
+    }
+
+    /// `dimensions` are the ones of `viewport` in pixels.
+    pub fn generate_mvp_mats(
+        &self,
+        scene: &Scene,
+        viewport: &Viewport,
+        dimensions: [u32; 2],
+    ) -> [[[f32; 4]; 4]; 3] {
+        let model = self.world_mat4();
+        let cam = scene.camera_for(self, viewport);
         let view = look_at_rh(cam.position, cam.center, cam.up);
         let aspect_ratio = dimensions[0] as f32 / dimensions[1] as f32;
-        let projection = perspective_rh_no(
-            cam.perspective[0],
-            aspect_ratio,
-            cam.perspective[1],
-            cam.perspective[2],
-        );
-        [model, view, projection]
+        [model, view, cam.projection.to_mat4(aspect_ratio)]
     }
 }
 
@@ -137,6 +732,7 @@ pub enum TvkObject<'a> {
     FloatLiteral(f32),
     Atom(&'a str),
     UIntLiteral(u32),
+    StringLiteral(&'a str),
     Color(Color),
     List(Vec<TvkObject<'a>>),
 }
@@ -147,7 +743,7 @@ pub enum InnerType {
     UInt(u32),
     Position(Position),
     UV([f32; 2]),
-    Rotate((Angle, Vec3)),
+    TransformOp(TransformOp),
     Topology(String),
     Color(Color),
     Vec3([f32; 3]),
@@ -156,9 +752,16 @@ pub enum InnerType {
     VertexBuffer(VertexBuffer),
     TexVertexBuffer(TexVertexBuffer),
     IndexBuffer(IndexBuffer),
-    Perspective([f32; 3]),
+    Projection(Projection),
     Camera(Camera),
     Transform(Transform),
     Model(Model),
-    Texture((Vec<u8>, ImageDimensions)),
+    Node(Node),
+    Texture(Arc<Texture>),
+    Sampler(SamplerConfig),
+    Material(Material),
+    Light(Light),
+    Normals(NormalMode),
+    Mesh(Mesh),
+    List(Vec<InnerType>),
 }
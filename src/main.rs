@@ -1,19 +1,23 @@
+mod assets;
+mod camera_controls;
 mod language;
 mod rendering_pipeline;
+mod texture_loader;
 mod tvk_glm;
 mod ui;
-use std::collections::HashMap;
 
-use ui::{AppInfo, Application};
+use std::time::Instant;
+
+use language::program::Program;
+use ui::{AppInfo, Application, PanelState};
 use winit::{
-    event::{Event, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, MouseButton, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
 };
 
 fn main() {
     let event_loop = EventLoop::new();
     let mut code = CODE.to_string();
-    let mut console = CONSOLE.to_string();
     let mut app = Application::new(&event_loop);
     let win_size = app.windows.get_primary_window().unwrap().inner_size();
     let mut app_info = AppInfo::new(
@@ -22,19 +26,18 @@ fn main() {
         win_size.width as f32 / 5.0,
         app.windows.get_primary_window().unwrap().scale_factor(),
     );
-    let mut nu_pars = language::parser::Parser::new(&code);
-    let exprs = nu_pars.parse();
-    let mut nu_int = language::interpreter::Interpreter {
-        bindings: HashMap::new(),
-    };
-    for e in &exprs {
-        nu_int.eval(e, &mut app.pipeline);
-    }
+    let mut program = Program::new();
+    let (mode, errors) = program.load(&code, &mut app.pipeline);
+    app.interpreting_mode = mode;
+    let mut console = console_output(&errors);
+    write_exports(&mut program, false, &mut console);
+    let mut cursor = [0.0, 0.0];
+    let mut last_frame = Instant::now();
     event_loop.run(move |event, _, control_flow| {
         let renderer = app.windows.get_primary_renderer_mut().unwrap();
         match event {
             Event::WindowEvent { event, window_id } if window_id == renderer.window().id() => {
-                let _pass = !app.gui.update(&event);
+                let pass = !app.gui.update(&event);
 
                 match event {
                     WindowEvent::Resized(size) => {
@@ -51,6 +54,54 @@ fn main() {
                             (new_inner_size.width as f32, new_inner_size.height as f32);
                         app_info.resize([width, height]);
                     }
+                    WindowEvent::CursorMoved { position, .. } => {
+                        cursor = [position.x as f32, position.y as f32];
+                        let controls = &mut app.camera_controls;
+                        app.pipeline.move_camera(|camera| controls.cursor_moved(cursor, camera));
+                    }
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button,
+                        ..
+                    } if pass => {
+                        app.camera_controls.press(button);
+                    }
+                    // Released everywhere, the drag may end over the panel.
+                    WindowEvent::MouseInput {
+                        state: ElementState::Released,
+                        button,
+                        ..
+                    } => {
+                        let clicked = app.camera_controls.release(button);
+                        if clicked && button == MouseButton::Left {
+                            let size = renderer.window().inner_size();
+                            app.picked = app
+                                .pipeline
+                                .pick(cursor, [size.width, size.height])
+                                .map(|i| Application::model_label(i, &app.pipeline.models[i]));
+                        }
+                    }
+                    WindowEvent::MouseWheel { delta, .. } if pass => {
+                        let controls = &mut app.camera_controls;
+                        app.pipeline.move_camera(|camera| controls.scroll(delta, camera));
+                    }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(key),
+                                state,
+                                ..
+                            },
+                        ..
+                    } if pass || state == ElementState::Released => {
+                        app.camera_controls.key(key, state == ElementState::Pressed);
+                    }
+                    WindowEvent::ModifiersChanged(modifiers) => {
+                        app.camera_controls.set_shift(modifiers.shift());
+                    }
+                    WindowEvent::Focused(false) => {
+                        app.camera_controls.release_all();
+                    }
                     WindowEvent::CloseRequested => {
                         *control_flow = ControlFlow::Exit;
                     }
@@ -59,26 +110,24 @@ fn main() {
             }
             Event::RedrawRequested(window_id) if window_id == window_id => {
                 app.gui.immediate_ui(|gui| {
-                    Application::gui_panel(
-                        &mut app.changed_input,
-                        &mut app_info,
-                        &mut app.pipeline.vk_ratio,
-                        &mut code,
-                        &mut console,
-                        gui,
-                    );
+                    let mut state = PanelState {
+                        code: &mut code,
+                        console: &mut console,
+                        program: &mut program,
+                        models: &app.pipeline.models,
+                        picked: &mut app.picked,
+                        view_camera: &mut app.pipeline.scene.view_camera,
+                        mode: app.interpreting_mode,
+                        changed: &mut app.changed_input,
+                        run: &mut app.run_requested,
+                    };
+                    Application::gui_panel(&mut state, &mut app_info, &mut app.pipeline.vk_ratio, gui);
                     if app.changed_input {
-                        app.pipeline.models.clear();
-                        app.pipeline.vbs.clear();
                         app.changed_input = false;
-                        let mut nu_pars = language::parser::Parser::new(&code);
-                        let exprs = nu_pars.parse();
-                        let mut nu_int = language::interpreter::Interpreter {
-                            bindings: HashMap::new(),
-                        };
-                        for e in &exprs {
-                            nu_int.eval(e, &mut app.pipeline);
-                        }
+                        let (mode, errors) = program.load(&code, &mut app.pipeline);
+                        app.interpreting_mode = mode;
+                        console = console_output(&errors);
+                        write_exports(&mut program, app.run_requested, &mut console);
                     }
                 });
                 let before_future = renderer.acquire().unwrap();
@@ -90,6 +139,15 @@ fn main() {
                 renderer.present(after_future, true);
             }
             Event::MainEventsCleared => {
+                let now = Instant::now();
+                let dt = (now - last_frame).as_secs_f32();
+                last_frame = now;
+                let controls = &mut app.camera_controls;
+                app.pipeline.move_camera(|camera| controls.update(dt, camera));
+                for error in program.tick(dt, &mut app.pipeline) {
+                    console.push('\n');
+                    console.push_str(&error);
+                }
                 renderer.window().request_redraw();
             }
             _ => (),
@@ -97,6 +155,31 @@ fn main() {
     });
 }
 
+/// Writes the exports of the last evaluation when it was run on purpose,
+/// otherwise only says they are waiting for Ctrl+Enter.
+fn write_exports(program: &mut Program, run: bool, console: &mut String) {
+    for export in program.exports.drain(..) {
+        let path = export.path.display();
+        if !run {
+            console.push_str(&format!("\nexport to {} waits for Ctrl+Enter", path));
+            continue;
+        }
+        match export.write() {
+            Ok(()) => console.push_str(&format!("\nexported {}", path)),
+            Err(e) => console.push_str(&format!("\nexport: {}", e)),
+        }
+    }
+}
+
+fn console_output(errors: &[String]) -> String {
+    let mut console = CONSOLE.to_string();
+    for error in errors {
+        console.push('\n');
+        console.push_str(error);
+    }
+    console
+}
+
 const CODE: &str = r#"(config (primitive triangle-list)
 (interpreting-mode manual))
 (def p1 (position
@@ -163,10 +246,11 @@ const CODE: &str = r#"(config (primitive triangle-list)
   (center (x 0.0) (y 0.0) (z 0.0))
   (up (x 0.0) (y 1.0) (z 0.0))
   (perspective pers)))
+(use-camera cam1)
 (def tex (texture textures/diamond.png))
 (def m1 (model
   vb ib (topology default)
-  (transform default) cam1 tex))
+  (transform default) tex))
 (draw m1)"#;
 
 const CONSOLE: &str = "vk-repl> ";
@@ -1,17 +1,20 @@
 use crate::language::types;
+use crate::texture_loader::{self, Compression};
+use crate::tvk_glm::{identity_mat4, intersect::Ray, unproject_zo};
 use egui_winit_vulkano::Gui;
 use std::{
     collections::{BTreeMap, HashMap},
     convert::TryFrom,
     sync::Arc,
-    io::Cursor,
+    path::PathBuf,
+    time::SystemTime,
 };
 use vulkano::{
-    buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess},
+    buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool, DeviceLocalBuffer, TypedBufferAccess},
     command_buffer::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
-        CommandBufferInheritanceInfo, CommandBufferUsage, RenderPassBeginInfo,
-        SecondaryAutoCommandBuffer, SubpassContents,
+        CommandBufferInheritanceInfo, CommandBufferUsage, PrimaryAutoCommandBuffer,
+        RenderPassBeginInfo, SecondaryAutoCommandBuffer, SubpassContents,
     },
     descriptor_set::{
         allocator::StandardDescriptorSetAllocator,
@@ -22,22 +25,38 @@ use vulkano::{
     device::{Device, Queue},
     format::Format,
     image::{view::ImageView, ImageDimensions, AttachmentImage, ImageAccess, ImageViewAbstract, SampleCount, ImmutableImage, MipmapsCount},
-    memory::allocator::StandardMemoryAllocator,
+    memory::allocator::{MemoryUsage, StandardMemoryAllocator},
     pipeline::{
         graphics::{
+            color_blend::ColorBlendState,
             input_assembly::{InputAssemblyState, PrimitiveTopology},
             multisample::MultisampleState,
             vertex_input::BuffersDefinition,
-            viewport::{Viewport, ViewportState},
+            viewport::{Scissor, Viewport, ViewportState},
         },
         GraphicsPipeline, Pipeline,
     },
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     shader::{ShaderStages, ShaderModule},
-    sync::GpuFuture, sampler::{Sampler, SamplerCreateInfo, Filter, SamplerMipmapMode, SamplerAddressMode},
+    sync::GpuFuture, sampler::{Sampler, SamplerCreateInfo, Filter, SamplerMipmapMode, SamplerAddressMode, LOD_CLAMP_NONE},
 };
 use vulkano_util::renderer::SwapchainImageView;
-use png;
+
+/// What the forms that don't depend on the clock left in the scene, see
+/// `Program::load`.
+struct StaticScene {
+    models: usize,
+    vbs: usize,
+    lights: usize,
+    viewports: usize,
+    camera: types::Camera,
+}
+
+struct TextureEntry {
+    modified: SystemTime,
+    texture: Arc<types::Texture>,
+    view: Option<Arc<ImageView<ImmutableImage>>>,
+}
 
 pub struct MSAAPipeline {
     pub allocator: Arc<StandardMemoryAllocator>,
@@ -49,9 +68,24 @@ pub struct MSAAPipeline {
     intermediary: Arc<ImageView<AttachmentImage>>,
     pub models: Vec<types::Model>,
     pub vbs: Vec<Vec<types::Vertex>>,
-    sampler: Arc<Sampler>,
+    pub lights: Vec<types::Light>,
+    /// Uniforms written every frame. Each draw takes a new chunk, so frames
+    /// still in flight keep reading their own.
+    mvp_pool: CpuBufferPool<[[[f32; 4]; 4]; 3]>,
+    material_pool: CpuBufferPool<types::MaterialUniform>,
+    lights_pool: CpuBufferPool<types::LightsUniform>,
+    vb_buffers: Vec<Arc<DeviceLocalBuffer<[types::Vertex]>>>,
+    vb_descriptor_set: Arc<PersistentDescriptorSet>,
+    textures: HashMap<(PathBuf, types::ColorSpace), TextureEntry>,
+    samplers: HashMap<types::SamplerConfig, Arc<Sampler>>,
     command_buffer_allocator: StandardCommandBufferAllocator,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
     pub vk_ratio: f32,
+    pub scene: types::Scene,
+    static_scene: Option<StaticScene>,
+    /// Animated models drawn last frame, their buffers are reused by the new
+    /// ones when the geometry didn't change.
+    previous_animated: Vec<types::Model>,
 }
 
 impl MSAAPipeline {
@@ -109,40 +143,24 @@ impl MSAAPipeline {
         );
         pipelines.insert("RESERVED_TRIANGLE_LIST".to_string(), pipeline);
 
-        let (pipeline, subpass) = Self::create_tex_pipeline(
-            queue.device().clone(),
-            render_pass.clone(),
-            PrimitiveTopology::PointList,
-        );
-        pipelines.insert("RESERVED_POINT_LIST_TEX".to_string(), pipeline);
-
-        let (pipeline, _) = Self::create_tex_pipeline(
-            queue.device().clone(),
-            render_pass.clone(),
-            PrimitiveTopology::LineList,
-        );
-        pipelines.insert("RESERVED_LINE_LIST_TEX".to_string(), pipeline);
-
-        let (pipeline, _) = Self::create_tex_pipeline(
-            queue.device().clone(),
-            render_pass.clone(),
-            PrimitiveTopology::LineStrip,
-        );
-        pipelines.insert("RESERVED_LINE_STRIP_TEX".to_string(), pipeline);
-
-        let (pipeline, _) = Self::create_tex_pipeline(
-            queue.device().clone(),
-            render_pass.clone(),
-            PrimitiveTopology::TriangleStrip,
-        );
-        pipelines.insert("RESERVED_TRIANGLE_STRIP_TEX".to_string(), pipeline);
-
-        let (pipeline, _) = Self::create_tex_pipeline(
-            queue.device().clone(),
-            render_pass.clone(),
-            PrimitiveTopology::TriangleList,
-        );
-        pipelines.insert("RESERVED_TRIANGLE_LIST_TEX".to_string(), pipeline);
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        for (topology, name) in [
+            (PrimitiveTopology::PointList, "RESERVED_POINT_LIST"),
+            (PrimitiveTopology::LineList, "RESERVED_LINE_LIST"),
+            (PrimitiveTopology::LineStrip, "RESERVED_LINE_STRIP"),
+            (PrimitiveTopology::TriangleStrip, "RESERVED_TRIANGLE_STRIP"),
+            (PrimitiveTopology::TriangleList, "RESERVED_TRIANGLE_LIST"),
+        ] {
+            for blend in [types::BlendMode::Opaque, types::BlendMode::Alpha, types::BlendMode::Additive] {
+                let (pipeline, _) = Self::create_tex_pipeline(
+                    queue.device().clone(),
+                    render_pass.clone(),
+                    topology,
+                    blend,
+                );
+                pipelines.insert(Self::tex_pipeline_name(name, blend), pipeline);
+            }
+        }
 
         let command_buffer_allocator =
             StandardCommandBufferAllocator::new(queue.device().clone(), Default::default());
@@ -152,17 +170,31 @@ impl MSAAPipeline {
         )
         .unwrap();
 
-        let sampler = Sampler::new(
-            queue.device().clone(),
-            SamplerCreateInfo {
-                mag_filter: Filter::Linear,
-                min_filter: Filter::Linear,
-                mipmap_mode: SamplerMipmapMode::Nearest,
-                address_mode: [SamplerAddressMode::Repeat; 3],
-                mip_lod_bias: 0.0,
-                ..Default::default()
+        let descriptor_set_allocator = StandardDescriptorSetAllocator::new(queue.device().clone());
+        let vb_unibuffer = CpuAccessibleBuffer::from_data(
+            allocator,
+            BufferUsage {
+                uniform_buffer: true,
+                ..BufferUsage::empty()
             },
-            ).unwrap();
+            false,
+            [identity_mat4(); 3],
+        )
+        .unwrap();
+        let vb_descriptor_set = PersistentDescriptorSet::new(
+            &descriptor_set_allocator,
+            pipelines["RESERVED_TRIANGLE_LIST"].layout().set_layouts().first().unwrap().clone(),
+            [WriteDescriptorSet::buffer(0, vb_unibuffer)],
+        )
+        .unwrap();
+
+        let uniform_usage = BufferUsage {
+            uniform_buffer: true,
+            ..BufferUsage::empty()
+        };
+        let mvp_pool = CpuBufferPool::new(allocator.clone(), uniform_usage, MemoryUsage::Upload);
+        let material_pool = CpuBufferPool::new(allocator.clone(), uniform_usage, MemoryUsage::Upload);
+        let lights_pool = CpuBufferPool::new(allocator.clone(), uniform_usage, MemoryUsage::Upload);
 
         Self {
             allocator: allocator.clone(),
@@ -174,9 +206,20 @@ impl MSAAPipeline {
             intermediary,
             models: Vec::new(),
             vbs: Vec::new(),
-            sampler,
+            lights: Vec::new(),
+            mvp_pool,
+            material_pool,
+            lights_pool,
+            vb_buffers: Vec::new(),
+            vb_descriptor_set,
+            textures: HashMap::new(),
+            samplers: HashMap::new(),
             command_buffer_allocator,
+            descriptor_set_allocator,
             vk_ratio: 1.0,
+            scene: types::Scene::default(),
+            static_scene: None,
+            previous_animated: Vec::new(),
         }
     }
 
@@ -184,9 +227,9 @@ impl MSAAPipeline {
         match primitive {
             PrimitiveTopology::PointList => "RESERVED_POINT_LIST",
             PrimitiveTopology::LineList => "RESERVED_LINE_LIST",
-            PrimitiveTopology::LineStrip => "RESERVED_LINE_LIST",
+            PrimitiveTopology::LineStrip => "RESERVED_LINE_STRIP",
             PrimitiveTopology::TriangleList => "RESERVED_TRIANGLE_LIST",
-            PrimitiveTopology::TriangleStrip => "RESERVED_TRIANGLE_LIST",
+            PrimitiveTopology::TriangleStrip => "RESERVED_TRIANGLE_STRIP",
             _ => "",
         }
     }
@@ -199,6 +242,14 @@ impl MSAAPipeline {
         }
     }
 
+    fn tex_pipeline_name(topology: &str, blend: types::BlendMode) -> String {
+        match blend {
+            types::BlendMode::Opaque => format!("{}_TEX", topology),
+            types::BlendMode::Alpha => format!("{}_TEX_ALPHA", topology),
+            types::BlendMode::Additive => format!("{}_TEX_ADDITIVE", topology),
+        }
+    }
+
     pub fn get_specific_pipeline(&self, primitive: &String) -> Arc<GraphicsPipeline> {
         if let Some(pipeline) = self.pipelines.get(primitive) {
             return pipeline.clone();
@@ -215,6 +266,10 @@ impl MSAAPipeline {
         self.models.push(model);
     }
 
+    pub fn receive_light(&mut self, light: types::Light) {
+        self.lights.push(light);
+    }
+
     fn create_render_pass(
         device: Arc<Device>,
         format: Format,
@@ -297,7 +352,7 @@ impl MSAAPipeline {
                 .vertex_shader(vertex_module.entry_point("main").unwrap(), ())
                 .input_assembly_state(InputAssemblyState::new().topology(topology))
                 .fragment_shader(frag_module.entry_point("main").unwrap(), ())
-                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+                .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
                 .render_pass(subpass.clone())
                 .multisample_state(MultisampleState {
                     rasterization_samples: subpass.num_samples().unwrap(),
@@ -313,19 +368,31 @@ impl MSAAPipeline {
         device: Arc<Device>,
         render_pass: Arc<RenderPass>,
         topology: PrimitiveTopology,
+        blend: types::BlendMode,
         ) -> (Arc<GraphicsPipeline>, Subpass) {
         let subpass = Subpass::from(render_pass, 0).unwrap();
+        let color_blend_state = ColorBlendState::new(subpass.num_color_attachments());
+        let color_blend_state = match blend {
+            types::BlendMode::Opaque => color_blend_state,
+            types::BlendMode::Alpha => color_blend_state.blend_alpha(),
+            types::BlendMode::Additive => color_blend_state.blend_additive(),
+        };
 
         let vs = vs_tex::load(device.clone()).expect("failed to load shader module");
         let fs = fs_tex::load(device.clone()).expect("failed to load shader module");
 
         (
             GraphicsPipeline::start()
-                .vertex_input_state(BuffersDefinition::new().vertex::<types::TextureVertex>())
+                .vertex_input_state(
+                    BuffersDefinition::new()
+                        .vertex::<types::TextureVertex>()
+                        .instance::<types::InstanceData>(),
+                )
                 .vertex_shader(vs.entry_point("main").unwrap(), ())
                 .input_assembly_state(InputAssemblyState::new().topology(topology))
                 .fragment_shader(fs.entry_point("main").unwrap(), ())
-                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+                .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+                .color_blend_state(color_blend_state)
                 .render_pass(subpass.clone())
                 .multisample_state(MultisampleState {
                     rasterization_samples: subpass.num_samples().unwrap(),
@@ -341,6 +408,62 @@ impl MSAAPipeline {
         self.current_primitive = mode;
     }
 
+    pub fn use_camera(&mut self, camera: types::Camera) {
+        self.scene.camera = camera;
+    }
+
+    pub fn add_viewport(&mut self, viewport: types::Viewport) {
+        self.scene.viewports.push(viewport);
+    }
+
+    /// Runs `f` on the viewport camera, which starts as the active one. The
+    /// script cameras are overridden once `f` reports it moved it.
+    pub fn move_camera(&mut self, f: impl FnOnce(&mut types::Camera) -> bool) {
+        let mut camera = self.scene.active_camera();
+        if f(&mut camera) {
+            self.scene.view_camera = Some(camera);
+        }
+    }
+
+    /// Remembers the current scene as the part that stays the same every
+    /// frame.
+    pub fn mark_static(&mut self) {
+        self.static_scene = Some(StaticScene {
+            models: self.models.len(),
+            vbs: self.vbs.len(),
+            lights: self.lights.len(),
+            viewports: self.scene.viewports.len(),
+            camera: self.scene.camera,
+        });
+    }
+
+    /// Drops what was added since `mark_static`, before the animated forms add
+    /// it again.
+    pub fn restore_static(&mut self) {
+        let marked = match &self.static_scene {
+            Some(marked) => marked,
+            None => return,
+        };
+        self.previous_animated = self.models.split_off(marked.models.min(self.models.len()));
+        self.vbs.truncate(marked.vbs);
+        self.vb_buffers.truncate(marked.vbs);
+        self.lights.truncate(marked.lights);
+        self.scene.viewports.truncate(marked.viewports);
+        self.scene.camera = marked.camera;
+    }
+
+    pub fn clear_scene(&mut self) {
+        self.models.clear();
+        self.vbs.clear();
+        self.vb_buffers.clear();
+        self.lights.clear();
+        self.scene.camera = types::Camera::default();
+        self.scene.viewports.clear();
+        self.static_scene = None;
+        self.previous_animated.clear();
+        self.current_primitive = PrimitiveTopology::TriangleList;
+    }
+
     pub fn render(
         &mut self,
         before_future: Box<dyn GpuFuture>,
@@ -354,23 +477,7 @@ impl MSAAPipeline {
         )
         .unwrap();
 
-        for mut model in &mut self.models {
-            let mut texture: Option<Arc<ImageView<_>>> = None;
-
-            let texture = texture.get_or_insert_with(|| {
-                let image = ImmutableImage::from_iter(
-                    &self.allocator,
-                    model.texture_data.0.iter().cloned(),
-                    model.texture_data.1,
-                    MipmapsCount::One,
-                    Format::R8G8B8A8_SRGB,
-                    &mut builder,
-                    )
-                    .unwrap();
-                ImageView::new_default(image).unwrap()
-            });
-            model.texture = Some(texture.clone());
-        }
+        self.upload_resources(&mut builder);
 
         let dimensions = image.image().dimensions().width_height();
         let mut vk_dimensions = dimensions;
@@ -441,140 +548,463 @@ impl MSAAPipeline {
         after_future.boxed()
     }
 
-    fn record_vb_cmd(
+    fn upload_resources(
         &mut self,
-        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
-        vk_dimensions: [u32; 2],
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
     ) {
-        for vb in &self.vbs {
-            let vertex_buffer = CpuAccessibleBuffer::from_iter(
+        for vb in &self.vbs[self.vb_buffers.len()..] {
+            let vertex_buffer = DeviceLocalBuffer::from_iter(
                 &self.allocator,
+                vb.iter().cloned(),
                 BufferUsage {
                     vertex_buffer: true,
                     ..BufferUsage::empty()
                 },
-                false,
-                vb.iter().cloned(),
+                builder,
             )
             .expect("failed to create buffer");
-            builder
-                .bind_pipeline_graphics(self.get_current_pipeline().clone())
-                .set_viewport(
-                    0,
-                    vec![Viewport {
-                        origin: [0.0, 0.0],
-                        dimensions: [vk_dimensions[0] as f32, vk_dimensions[1] as f32],
-                        depth_range: 0.0..1.0,
-                    }],
-                )
-                .bind_vertex_buffers(0, vertex_buffer)
-                .draw(vb.len() as u32, 1, 0, 0)
-                .unwrap();
+            self.vb_buffers.push(vertex_buffer);
         }
+
+        let mut models = std::mem::take(&mut self.models);
+        let previous = std::mem::take(&mut self.previous_animated);
+        let first_animated = self.static_scene.as_ref().map_or(models.len(), |marked| marked.models);
+        for (i, model) in models.iter_mut().enumerate() {
+            if model.buffers.is_some() || model.vertices.is_empty() || model.indices.is_empty() {
+                continue;
+            }
+            let reused = i
+                .checked_sub(first_animated)
+                .and_then(|k| previous.get(k))
+                .filter(|old| old.shares_buffers_with(model))
+                .and_then(|old| old.buffers.clone());
+            match reused {
+                Some(buffers) => model.buffers = Some(buffers),
+                None => self.upload_model(model, builder),
+            }
+        }
+        self.models = models;
     }
 
-    fn record_model_cmd(
+    fn upload_model(
         &mut self,
+        model: &mut types::Model,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        let texture = self.texture_view(&model.material.base_color, builder);
+        let normal_map = model
+            .material
+            .normal_map
+            .as_ref()
+            .map(|normal_map| self.texture_view(normal_map, builder));
+        let vertices = DeviceLocalBuffer::from_iter(
+            &self.allocator,
+            model.vertices.iter().cloned(),
+            BufferUsage {
+                vertex_buffer: true,
+                ..BufferUsage::empty()
+            },
+            &mut *builder,
+        )
+        .expect("failed to create buffer");
+        let indices = DeviceLocalBuffer::from_iter(
+            &self.allocator,
+            model.indices.iter().cloned(),
+            BufferUsage {
+                index_buffer: true,
+                ..BufferUsage::empty()
+            },
+            &mut *builder,
+        )
+        .expect("failed to create buffer");
+        let instances = if model.instances.is_empty() {
+            vec![types::InstanceData::default()]
+        } else {
+            model.instances.clone()
+        };
+        let instances = DeviceLocalBuffer::from_iter(
+            &self.allocator,
+            instances,
+            BufferUsage {
+                vertex_buffer: true,
+                ..BufferUsage::empty()
+            },
+            &mut *builder,
+        )
+        .expect("failed to create buffer");
+        let sampler = self.sampler(model.material.sampler);
+        model.buffers = Some(types::ModelBuffers {
+            vertices,
+            indices,
+            instances,
+            texture,
+            normal_map,
+            sampler,
+        });
+    }
+
+    fn texture_view(
+        &mut self,
+        texture: &types::Texture,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Arc<ImageView<ImmutableImage>> {
+        match self.textures.get_mut(&(texture.path.clone(), texture.color_space)) {
+            Some(entry) => entry
+                .view
+                .get_or_insert_with(|| Self::upload_texture(&self.allocator, &entry.texture, builder))
+                .clone(),
+            None => Self::upload_texture(&self.allocator, texture, builder),
+        }
+    }
+
+    /// Uploads a texture. Formats that are guaranteed to support linear blits
+    /// get a full mip chain, which vulkano fills by blitting each level from
+    /// the previous one right after the copy.
+    fn upload_texture(
+        allocator: &Arc<StandardMemoryAllocator>,
+        texture: &types::Texture,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Arc<ImageView<ImmutableImage>> {
+        let mipmaps = match texture.format {
+            Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM | Format::R16G16B16A16_SFLOAT => {
+                MipmapsCount::Log2
+            }
+            _ => MipmapsCount::One,
+        };
+        let image = ImmutableImage::from_iter(
+            allocator,
+            texture.data.iter().cloned(),
+            texture.dimensions,
+            mipmaps,
+            texture.format,
+            builder,
+        )
+        .unwrap();
+        ImageView::new_default(image).unwrap()
+    }
+
+    pub fn sampler(&mut self, config: types::SamplerConfig) -> Arc<Sampler> {
+        let device = self.queue.device().clone();
+        self.samplers
+            .entry(config)
+            .or_insert_with(|| {
+                let filter = match config.filter {
+                    types::SamplerFilter::Nearest => Filter::Nearest,
+                    types::SamplerFilter::Linear => Filter::Linear,
+                };
+                let address_mode = match config.wrap {
+                    types::SamplerWrap::Repeat => SamplerAddressMode::Repeat,
+                    types::SamplerWrap::Clamp => SamplerAddressMode::ClampToEdge,
+                    types::SamplerWrap::Mirror => SamplerAddressMode::MirroredRepeat,
+                };
+                let max_anisotropy = device.physical_device().properties().max_sampler_anisotropy;
+                let anisotropy = if config.anisotropy > 1 && device.enabled_features().sampler_anisotropy {
+                    Some((config.anisotropy as f32).min(max_anisotropy))
+                } else {
+                    None
+                };
+                let (mipmap_mode, lod) = if config.mipmaps {
+                    let mode = match config.filter {
+                        types::SamplerFilter::Nearest => SamplerMipmapMode::Nearest,
+                        types::SamplerFilter::Linear => SamplerMipmapMode::Linear,
+                    };
+                    (mode, 0.0..=LOD_CLAMP_NONE)
+                } else {
+                    (SamplerMipmapMode::Nearest, 0.0..=0.0)
+                };
+                Sampler::new(
+                    device.clone(),
+                    SamplerCreateInfo {
+                        mag_filter: filter,
+                        min_filter: filter,
+                        mipmap_mode,
+                        address_mode: [address_mode; 3],
+                        mip_lod_bias: 0.0,
+                        anisotropy,
+                        lod,
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+            })
+            .clone()
+    }
+
+    /// Restricts the following draws to `pixels`, an `[x, y, width, height]`
+    /// rectangle.
+    fn set_view(
         builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
-        vk_dimensions: [u32; 2],
+        pixels: [u32; 4],
     ) {
-        for model in &self.models {
-            let vertex_buffer = CpuAccessibleBuffer::from_iter(
-                &self.allocator,
-                BufferUsage {
-                    vertex_buffer: true,
-                    ..BufferUsage::empty()
-                },
-                false,
-                model.vertices.iter().cloned(),
-            )
-            .expect("failed to create buffer");
-            let index_buffer = CpuAccessibleBuffer::from_iter(
-                &self.allocator,
-                BufferUsage {
-                    index_buffer: true,
-                    ..BufferUsage::empty()
-                },
-                false,
-                model.indices.iter().cloned(),
+        let [x, y, width, height] = pixels;
+        builder
+            .set_viewport(
+                0,
+                vec![Viewport {
+                    origin: [x as f32, y as f32],
+                    dimensions: [width as f32, height as f32],
+                    depth_range: 0.0..1.0,
+                }],
             )
-            .expect("failed to create buffer");
-            let ubo_matrix = model.generate_mvp_mats(vk_dimensions);
+            .set_scissor(
+                0,
+                vec![Scissor {
+                    origin: [x, y],
+                    dimensions: [width, height],
+                }],
+            );
+    }
 
-            let unibuffer = CpuAccessibleBuffer::from_data(
-                &self.allocator,
-                BufferUsage {
-                    uniform_buffer: true,
-                    ..BufferUsage::empty()
-                },
-                false,
-                [ubo_matrix],
-            )
-            .unwrap();
-            let desc_alloca = StandardDescriptorSetAllocator::new(self.queue.device().clone());
+    fn record_vb_cmd(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        vk_dimensions: [u32; 2],
+    ) {
+        let pipeline = self.get_current_pipeline();
+        for viewport in self.scene.viewports() {
+            let pixels = viewport.pixels(vk_dimensions);
+            if pixels[2] == 0 || pixels[3] == 0 {
+                continue;
+            }
+            for vertex_buffer in &self.vb_buffers {
+                builder.bind_pipeline_graphics(pipeline.clone());
+                Self::set_view(builder, pixels);
+                builder
+                    .bind_vertex_buffers(0, vertex_buffer.clone())
+                    .bind_descriptor_sets(
+                        vulkano::pipeline::PipelineBindPoint::Graphics,
+                        pipeline.layout().clone(),
+                        0,
+                        self.vb_descriptor_set.clone(),
+                    )
+                    .draw(vertex_buffer.len() as u32, 1, 0, 0)
+                    .unwrap();
+            }
+        }
+    }
 
+    fn record_model_cmd(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        vk_dimensions: [u32; 2],
+    ) {
+        let mut lights = types::LightsUniform::default();
+        let count = self.lights.len().min(types::MAX_LIGHTS);
+        lights.count = count as u32;
+        for (data, light) in lights.lights.iter_mut().zip(&self.lights[..count]) {
+            *data = light.into();
+        }
+        let lights_layout = self
+            .get_specific_pipeline(&"RESERVED_TRIANGLE_LIST_TEX".to_string())
+            .layout()
+            .set_layouts()
+            .get(1)
+            .unwrap()
+            .clone();
+        let lights_descriptor_set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            lights_layout,
+            [WriteDescriptorSet::buffer(0, self.lights_pool.from_data(lights).unwrap())],
+        )
+        .unwrap();
 
-            let desc_set = PersistentDescriptorSet::new(
-                &desc_alloca,
-                self.get_specific_pipeline(&"RESERVED_TRIANGLE_LIST_TEX".to_string()).layout().set_layouts().get(0).unwrap().clone(),
-                [
-                WriteDescriptorSet::buffer(0, unibuffer.clone()),
-                WriteDescriptorSet::image_view_sampler(1,
-                    model.texture.as_ref().unwrap().clone(), self.sampler.clone())
-                ],
-            )
-            .unwrap();
-            builder
-                .bind_pipeline_graphics(self.get_specific_pipeline(&"RESERVED_TRIANGLE_LIST_TEX".to_string()).clone())
-                .set_viewport(
-                    0,
-                    vec![Viewport {
-                        origin: [0.0, 0.0],
-                        dimensions: [vk_dimensions[0] as f32, vk_dimensions[1] as f32],
-                        depth_range: 0.0..1.0,
-                    }],
-                )
-                .bind_vertex_buffers(0, vertex_buffer.clone())
-                .bind_index_buffer(index_buffer.clone())
-                .bind_descriptor_sets(
-                    vulkano::pipeline::PipelineBindPoint::Graphics,
-                    self.get_specific_pipeline(&"RESERVED_TRIANGLE_LIST_TEX".to_string()).layout().clone(),
-                    0,
-                    desc_set.clone(),
+        // Blended models go last so they are composited over the opaque ones.
+        let opaque = self
+            .models
+            .iter()
+            .filter(|m| m.material.blend == types::BlendMode::Opaque);
+        let blended = self
+            .models
+            .iter()
+            .filter(|m| m.material.blend != types::BlendMode::Opaque);
+        let drawn: Vec<_> = opaque
+            .chain(blended)
+            .filter_map(|model| Some((model, model.buffers.as_ref()?)))
+            .map(|(model, buffers)| {
+                let material = self
+                    .material_pool
+                    .from_data(types::MaterialUniform {
+                        tint: model.material.tint,
+                        shininess: model.material.shininess,
+                        has_normal_map: buffers.normal_map.is_some() as u32,
+                        _pad: [0; 2],
+                    })
+                    .unwrap();
+                (model, buffers, material)
+            })
+            .collect();
+
+        for viewport in self.scene.viewports() {
+            let pixels = viewport.pixels(vk_dimensions);
+            if pixels[2] == 0 || pixels[3] == 0 {
+                continue;
+            }
+            for (model, buffers, material) in &drawn {
+                let pipeline = self.get_specific_pipeline(&Self::tex_pipeline_name(
+                    &model.topology,
+                    model.material.blend,
+                ));
+                let mvp = self
+                    .mvp_pool
+                    .from_data(model.generate_mvp_mats(&self.scene, viewport, [pixels[2], pixels[3]]))
+                    .unwrap();
+                let descriptor_set = PersistentDescriptorSet::new(
+                    &self.descriptor_set_allocator,
+                    pipeline.layout().set_layouts().first().unwrap().clone(),
+                    [
+                        WriteDescriptorSet::buffer(0, mvp),
+                        WriteDescriptorSet::image_view_sampler(
+                            1,
+                            buffers.texture.clone(),
+                            buffers.sampler.clone(),
+                        ),
+                        WriteDescriptorSet::buffer(2, material.clone()),
+                        // Without a normal map the base colour is bound in its place, the
+                        // shader ignores it when `has_normal_map` is zero.
+                        WriteDescriptorSet::image_view_sampler(
+                            3,
+                            buffers.normal_map.clone().unwrap_or_else(|| buffers.texture.clone()),
+                            buffers.sampler.clone(),
+                        ),
+                    ],
                 )
-                .draw_indexed(index_buffer.len() as u32, 1, 0, 0, 0)
                 .unwrap();
+                builder.bind_pipeline_graphics(pipeline.clone());
+                Self::set_view(builder, pixels);
+                builder
+                    .bind_vertex_buffers(0, (buffers.vertices.clone(), buffers.instances.clone()))
+                    .bind_index_buffer(buffers.indices.clone())
+                    .bind_descriptor_sets(
+                        vulkano::pipeline::PipelineBindPoint::Graphics,
+                        pipeline.layout().clone(),
+                        0,
+                        (descriptor_set, lights_descriptor_set.clone()),
+                    )
+                    .draw_indexed(buffers.indices.len() as u32, buffers.instances.len() as u32, 0, 0, 0)
+                    .unwrap();
+            }
         }
     }
 
 
-    pub fn load_texture_image(&self, path: &str) -> Option<(Vec<u8>, ImageDimensions)> {
-        let png_bytes = std::fs::read(path);
-        if let Err(_) = png_bytes {
-            return None;
-        } else {
-            let png_bytes = png_bytes.unwrap();
-            let cursor = Cursor::new(png_bytes);
-            let decoder = png::Decoder::new(cursor);
-            let mut reader = decoder.read_info().unwrap();
-            let info = reader.info();
-            let image_dimensions = ImageDimensions::Dim2d {
-                width: info.width,
-                height: info.height,
-                array_layers: 1,
-            };
-            let mut image_data = Vec::new();
-            let depth: u32 = match info.bit_depth {
-                png::BitDepth::One => 1,
-                png::BitDepth::Two => 2,
-                png::BitDepth::Four => 4,
-                png::BitDepth::Eight => 8,
-                png::BitDepth::Sixteen => 16,
-            };
-            image_data.resize((info.width * info.height * depth) as usize, 0);
-            reader.next_frame(&mut image_data).unwrap();
-            Some((image_data, image_dimensions))
+    /// Index in `models` of the closest model under `cursor`, given in physical
+    /// pixels from the top left corner of a `window` sized window.
+    pub fn pick(&self, cursor: [f32; 2], window: [u32; 2]) -> Option<usize> {
+        let vk_dimensions = [(window[0] as f32 * self.vk_ratio) as u32, window[1]];
+        // The last viewport is drawn on top of the others.
+        let (viewport, pixels) = self.scene.viewports().iter().rev().find_map(|viewport| {
+            let pixels = viewport.pixels(vk_dimensions);
+            let [x, y, width, height] = pixels.map(|p| p as f32);
+            let inside = (x..x + width).contains(&cursor[0]) && (y..y + height).contains(&cursor[1]);
+            inside.then_some((viewport, pixels))
+        })?;
+        let rect = pixels.map(|p| p as f32);
+        self.models
+            .iter()
+            .enumerate()
+            .filter_map(|(i, model)| {
+                let [_, view, projection] =
+                    model.generate_mvp_mats(&self.scene, viewport, [pixels[2], pixels[3]]);
+                let near = unproject_zo([cursor[0], cursor[1], 0.0], view, projection, rect)?;
+                let far = unproject_zo([cursor[0], cursor[1], 1.0], view, projection, rect)?;
+                let ray = Ray::between(near.into(), far.into());
+                model.intersect_ray(&ray).map(|t| (i, t))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Returns the texture at `path`, decoding it only if it isn't cached yet or
+    /// if the file changed since it was last read.
+    pub fn load_texture(
+        &mut self,
+        path: &str,
+        color_space: types::ColorSpace,
+    ) -> Result<Arc<types::Texture>, String> {
+        let path = std::fs::canonicalize(path).map_err(|e| format!("{}: {}", path, e))?;
+        let modified = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let key = (path, color_space);
+        if let Some(entry) = self.textures.get(&key) {
+            if entry.modified == modified {
+                return Ok(entry.texture.clone());
+            }
+        }
+        let decoded = texture_loader::decode(&key.0, color_space)?;
+        let features = self.queue.device().enabled_features();
+        let supported = match decoded.compression {
+            Compression::None => true,
+            Compression::Bc => features.texture_compression_bc,
+            Compression::Etc2 => features.texture_compression_etc2,
+            Compression::Astc => features.texture_compression_astc_ldr,
+        };
+        if !supported {
+            return Err(format!(
+                "{}: {:?} isn't supported by this device",
+                key.0.display(),
+                decoded.format
+            ));
         }
+        let texture = Arc::new(types::Texture {
+            path: key.0.clone(),
+            data: decoded.data,
+            dimensions: decoded.dimensions,
+            color_space,
+            format: decoded.format,
+        });
+        self.textures.insert(
+            key,
+            TextureEntry {
+                modified,
+                texture: texture.clone(),
+                view: None,
+            },
+        );
+        Ok(texture)
+    }
+
+    /// Registers RGBA8 pixels that don't live in their own file, such as images
+    /// embedded in a glTF scene. `key` only has to be unique, `modified` comes
+    /// from the containing file so the GPU copy is kept while it's unchanged.
+    pub fn embedded_texture(
+        &mut self,
+        key: PathBuf,
+        modified: SystemTime,
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+        color_space: types::ColorSpace,
+    ) -> Arc<types::Texture> {
+        let key = (key, color_space);
+        if let Some(entry) = self.textures.get(&key) {
+            if entry.modified == modified {
+                return entry.texture.clone();
+            }
+        }
+        let texture = Arc::new(types::Texture {
+            path: key.0.clone(),
+            data,
+            dimensions: ImageDimensions::Dim2d {
+                width,
+                height,
+                array_layers: 1,
+            },
+            color_space,
+            format: match color_space {
+                types::ColorSpace::Srgb => Format::R8G8B8A8_SRGB,
+                types::ColorSpace::Linear => Format::R8G8B8A8_UNORM,
+            },
+        });
+        self.textures.insert(
+            key,
+            TextureEntry {
+                modified,
+                texture: texture.clone(),
+                view: None,
+            },
+        );
+        texture
     }
 }
 
@@ -630,12 +1060,26 @@ layout(binding = 0) uniform UniformBufferObject {
 
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 instance_c0;
+layout(location = 3) in vec4 instance_c1;
+layout(location = 4) in vec4 instance_c2;
+layout(location = 5) in vec4 instance_c3;
+layout(location = 6) in vec3 normal;
 
 layout(location = 0) out vec2 tex_coords;
+layout(location = 1) out vec3 world_position;
+layout(location = 2) out vec3 world_normal;
+layout(location = 3) out vec3 camera_position;
 
 void main() {
-    gl_Position = ubo.proj * ubo.view * ubo.model * vec4(position, 1.0);
+    mat4 instance = mat4(instance_c0, instance_c1, instance_c2, instance_c3);
+    mat4 world = instance * ubo.model;
+    vec4 world_pos = world * vec4(position, 1.0);
+    gl_Position = ubo.proj * ubo.view * world_pos;
     tex_coords = uv;
+    world_position = world_pos.xyz;
+    world_normal = transpose(inverse(mat3(world))) * normal;
+    camera_position = inverse(ubo.view)[3].xyz;
 }"
     }
 }
@@ -645,14 +1089,96 @@ mod fs_tex {
         ty: "fragment",
         src: "
 #version 450
+#define MAX_LIGHTS 8
+#define AMBIENT 0.0
+#define DIRECTIONAL 1.0
+#define POINT 2.0
+#define SPOT 3.0
+
 layout(location = 0) in vec2 tex_coords;
+layout(location = 1) in vec3 world_position;
+layout(location = 2) in vec3 world_normal;
+layout(location = 3) in vec3 camera_position;
 layout(location = 0) out vec4 f_color;
 
 layout(set = 0, binding = 1) uniform sampler2D tex;
+layout(set = 0, binding = 2) uniform MaterialData {
+    vec4 tint;
+    float shininess;
+    uint has_normal_map;
+} material;
+layout(set = 0, binding = 3) uniform sampler2D normal_map;
+
+struct Light {
+    vec4 position;
+    vec4 direction;
+    vec4 color;
+};
+
+layout(set = 1, binding = 0) uniform LightData {
+    uint count;
+    Light lights[MAX_LIGHTS];
+} lights;
+
+// Tangent frame from screen space derivatives, so normal maps work on meshes
+// that don't carry tangents.
+mat3 cotangent_frame(vec3 n, vec3 p, vec2 uv) {
+    vec3 dp1 = dFdx(p);
+    vec3 dp2 = dFdy(p);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+    vec3 dp2perp = cross(dp2, n);
+    vec3 dp1perp = cross(n, dp1);
+    vec3 t = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 b = dp2perp * duv1.y + dp1perp * duv2.y;
+    float invmax = inversesqrt(max(dot(t, t), dot(b, b)));
+    return mat3(t * invmax, b * invmax, n);
+}
 
 void main() {
-    f_color = texture(tex, tex_coords);
+    vec4 base = texture(tex, tex_coords) * material.tint;
+    if (lights.count == 0 || length(world_normal) < 0.0001) {
+        f_color = base;
+        return;
+    }
+
+    vec3 n = normalize(world_normal);
+    if (material.has_normal_map != 0) {
+        vec3 mapped = texture(normal_map, tex_coords).xyz * 2.0 - 1.0;
+        n = normalize(cotangent_frame(n, world_position, tex_coords) * mapped);
+    }
+    vec3 view_dir = normalize(camera_position - world_position);
+
+    vec3 color = vec3(0.0);
+    for (uint i = 0; i < lights.count; i++) {
+        Light light = lights.lights[i];
+        vec3 radiance = light.color.rgb * light.color.w;
+        if (light.position.w == AMBIENT) {
+            color += radiance * base.rgb;
+            continue;
+        }
+
+        vec3 l;
+        float attenuation = 1.0;
+        if (light.position.w == DIRECTIONAL) {
+            l = normalize(-light.direction.xyz);
+        } else {
+            vec3 to_light = light.position.xyz - world_position;
+            float dist = length(to_light);
+            l = to_light / dist;
+            attenuation = 1.0 / (1.0 + 0.09 * dist + 0.032 * dist * dist);
+            if (light.position.w == SPOT) {
+                float theta = dot(l, normalize(-light.direction.xyz));
+                attenuation *= smoothstep(light.direction.w, light.direction.w + 0.05, theta);
+            }
+        }
+
+        float diffuse = max(dot(n, l), 0.0);
+        vec3 h = normalize(l + view_dir);
+        float specular = diffuse > 0.0 ? pow(max(dot(n, h), 0.0), material.shininess) : 0.0;
+        color += attenuation * radiance * (diffuse * base.rgb + specular);
+    }
+    f_color = vec4(color, base.a);
 }"
     }
 }
-
@@ -0,0 +1,315 @@
+use std::{
+    ffi::OsStr,
+    io::Cursor,
+    path::Path,
+};
+
+use image::{codecs::hdr::HdrDecoder, DynamicImage, ImageFormat};
+use vulkano::{format::Format, image::ImageDimensions};
+
+use crate::language::types::ColorSpace;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// Block compression family of a precompressed format. Each one needs its own
+/// device feature before images can be created with it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Bc,
+    Etc2,
+    Astc,
+}
+
+pub struct DecodedTexture {
+    pub data: Vec<u8>,
+    pub dimensions: ImageDimensions,
+    pub format: Format,
+    pub compression: Compression,
+}
+
+/// Decodes the image at `path`. The container is picked from its magic bytes,
+/// falling back to the file extension for formats without one (e.g. TGA).
+pub fn decode(path: &Path, color_space: ColorSpace) -> Result<DecodedTexture, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let extension = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_lowercase);
+
+    let result = if bytes.starts_with(&KTX2_IDENTIFIER) || extension.as_deref() == Some("ktx2") {
+        decode_ktx2(&bytes)
+    } else if bytes.starts_with(&PNG_SIGNATURE) {
+        decode_png(bytes, color_space)
+    } else {
+        decode_image(&bytes, path, color_space)
+    };
+    result.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn rgba8_format(color_space: ColorSpace) -> Format {
+    match color_space {
+        ColorSpace::Srgb => Format::R8G8B8A8_SRGB,
+        ColorSpace::Linear => Format::R8G8B8A8_UNORM,
+    }
+}
+
+fn dimensions_2d(width: u32, height: u32) -> ImageDimensions {
+    ImageDimensions::Dim2d {
+        width,
+        height,
+        array_layers: 1,
+    }
+}
+
+/// Decodes a PNG of any colour type and bit depth into 8-bit RGBA.
+fn decode_png(bytes: Vec<u8>, color_space: ColorSpace) -> Result<DecodedTexture, String> {
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    if info.bit_depth != png::BitDepth::Eight {
+        return Err(format!("unsupported bit depth {:?}", info.bit_depth));
+    }
+    let pixels = &buffer[..info.buffer_size()];
+    let data: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err("palette wasn't expanded".to_string()),
+    };
+    Ok(DecodedTexture {
+        data,
+        dimensions: dimensions_2d(info.width, info.height),
+        format: rgba8_format(color_space),
+        compression: Compression::None,
+    })
+}
+
+/// Decodes anything the `image` crate understands (JPEG, BMP, TGA, HDR, EXR,
+/// DDS...). HDR and EXR images keep their range and are uploaded as 32-bit
+/// floats.
+fn decode_image(bytes: &[u8], path: &Path, color_space: ColorSpace) -> Result<DecodedTexture, String> {
+    let format = image::guess_format(bytes)
+        .or_else(|_| ImageFormat::from_path(path))
+        .map_err(|e| e.to_string())?;
+    if format == ImageFormat::Hdr {
+        return decode_hdr(bytes);
+    }
+    let image = image::load_from_memory_with_format(bytes, format).map_err(|e| e.to_string())?;
+    let dimensions = dimensions_2d(image.width(), image.height());
+    match image {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+            let pixels = image.to_rgba32f().into_raw();
+            Ok(DecodedTexture {
+                data: bytemuck::cast_slice(&pixels).to_vec(),
+                dimensions,
+                format: Format::R32G32B32A32_SFLOAT,
+                compression: Compression::None,
+            })
+        }
+        _ => Ok(DecodedTexture {
+            data: image.to_rgba8().into_raw(),
+            dimensions,
+            format: rgba8_format(color_space),
+            compression: Compression::None,
+        }),
+    }
+}
+
+/// `image` tone maps Radiance files down to 8 bits, their own decoder keeps the
+/// floats.
+fn decode_hdr(bytes: &[u8]) -> Result<DecodedTexture, String> {
+    let decoder = HdrDecoder::new(bytes).map_err(|e| e.to_string())?;
+    let meta = decoder.metadata();
+    let pixels = decoder.read_image_hdr().map_err(|e| e.to_string())?;
+    let data: Vec<f32> = pixels.iter().flat_map(|p| [p[0], p[1], p[2], 1.0]).collect();
+    Ok(DecodedTexture {
+        data: bytemuck::cast_slice(&data).to_vec(),
+        dimensions: dimensions_2d(meta.width, meta.height),
+        format: Format::R32G32B32A32_SFLOAT,
+        compression: Compression::None,
+    })
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "truncated KTX2 header".to_string())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, String> {
+    Ok(read_u32(bytes, offset)? as u64 | (read_u32(bytes, offset + 4)? as u64) << 32)
+}
+
+/// Maps the raw `VkFormat` stored in a KTX2 header to the formats we can upload.
+fn ktx2_format(vk_format: u32) -> Option<(Format, Compression)> {
+    Some(match vk_format {
+        37 => (Format::R8G8B8A8_UNORM, Compression::None),
+        43 => (Format::R8G8B8A8_SRGB, Compression::None),
+        97 => (Format::R16G16B16A16_SFLOAT, Compression::None),
+        109 => (Format::R32G32B32A32_SFLOAT, Compression::None),
+        131 => (Format::BC1_RGB_UNORM_BLOCK, Compression::Bc),
+        132 => (Format::BC1_RGB_SRGB_BLOCK, Compression::Bc),
+        133 => (Format::BC1_RGBA_UNORM_BLOCK, Compression::Bc),
+        134 => (Format::BC1_RGBA_SRGB_BLOCK, Compression::Bc),
+        137 => (Format::BC3_UNORM_BLOCK, Compression::Bc),
+        138 => (Format::BC3_SRGB_BLOCK, Compression::Bc),
+        139 => (Format::BC4_UNORM_BLOCK, Compression::Bc),
+        141 => (Format::BC5_UNORM_BLOCK, Compression::Bc),
+        145 => (Format::BC7_UNORM_BLOCK, Compression::Bc),
+        146 => (Format::BC7_SRGB_BLOCK, Compression::Bc),
+        151 => (Format::ETC2_R8G8B8A8_UNORM_BLOCK, Compression::Etc2),
+        152 => (Format::ETC2_R8G8B8A8_SRGB_BLOCK, Compression::Etc2),
+        157 => (Format::ASTC_4x4_UNORM_BLOCK, Compression::Astc),
+        158 => (Format::ASTC_4x4_SRGB_BLOCK, Compression::Astc),
+        _ => return None,
+    })
+}
+
+/// Reads the base level of a KTX2 container. Supercompressed files (Basis,
+/// zstd) aren't supported, the data must already be in a GPU format.
+fn decode_ktx2(bytes: &[u8]) -> Result<DecodedTexture, String> {
+    if !bytes.starts_with(&KTX2_IDENTIFIER) {
+        return Err("not a KTX2 file".to_string());
+    }
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?.max(1);
+    let supercompression = read_u32(bytes, 44)?;
+    if supercompression != 0 {
+        return Err(format!("unsupported supercompression scheme {}", supercompression));
+    }
+    let (format, compression) =
+        ktx2_format(vk_format).ok_or_else(|| format!("unsupported VkFormat {}", vk_format))?;
+    let offset = read_u64(bytes, 80)? as usize;
+    let length = read_u64(bytes, 88)? as usize;
+    let data = offset
+        .checked_add(length)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or_else(|| "truncated KTX2 level data".to_string())?
+        .to_vec();
+    Ok(DecodedTexture {
+        data,
+        dimensions: dimensions_2d(width, height),
+        format,
+        compression,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_png(
+        width: u32,
+        height: u32,
+        color_type: png::ColorType,
+        bit_depth: png::BitDepth,
+        palette: Option<&[u8]>,
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(bit_depth);
+        if let Some(palette) = palette {
+            encoder.set_palette(palette.to_vec());
+        }
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(pixels).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    #[test]
+    fn png_expands_to_rgba8() {
+        let rgb = encode_png(2, 1, png::ColorType::Rgb, png::BitDepth::Eight, None, &[1, 2, 3, 4, 5, 6]);
+        let decoded = decode_png(rgb, ColorSpace::Srgb).unwrap();
+        assert_eq!(decoded.data, [1, 2, 3, 255, 4, 5, 6, 255]);
+        assert_eq!(decoded.format, Format::R8G8B8A8_SRGB);
+        assert_eq!(decoded.dimensions, dimensions_2d(2, 1));
+
+        let gray_alpha = encode_png(1, 1, png::ColorType::GrayscaleAlpha, png::BitDepth::Eight, None, &[7, 8]);
+        let decoded = decode_png(gray_alpha, ColorSpace::Linear).unwrap();
+        assert_eq!(decoded.data, [7, 7, 7, 8]);
+        assert_eq!(decoded.format, Format::R8G8B8A8_UNORM);
+    }
+
+    #[test]
+    fn png_strips_16_bit_and_expands_palettes() {
+        let gray16 = encode_png(1, 1, png::ColorType::Grayscale, png::BitDepth::Sixteen, None, &[0xAB, 0xCD]);
+        assert_eq!(decode_png(gray16, ColorSpace::Srgb).unwrap().data, [0xAB, 0xAB, 0xAB, 255]);
+
+        let palette = [10, 20, 30, 40, 50, 60];
+        let indexed = encode_png(2, 1, png::ColorType::Indexed, png::BitDepth::Eight, Some(&palette), &[1, 0]);
+        assert_eq!(
+            decode_png(indexed, ColorSpace::Srgb).unwrap().data,
+            [40, 50, 60, 255, 10, 20, 30, 255]
+        );
+    }
+
+    #[test]
+    fn hdr_keeps_its_range_as_32_bit_floats() {
+        let mut bytes = Vec::new();
+        let pixels = [image::Rgb([2.0, 0.5, 0.0]), image::Rgb([8.0, 1.0, 0.25])];
+        image::codecs::hdr::HdrEncoder::new(&mut bytes).encode(&pixels, 2, 1).unwrap();
+        let decoded = decode_image(&bytes, Path::new("sky.hdr"), ColorSpace::Srgb).unwrap();
+        assert_eq!(decoded.format, Format::R32G32B32A32_SFLOAT);
+        let floats: &[f32] = bytemuck::cast_slice(&decoded.data);
+        assert_eq!(floats, [2.0, 0.5, 0.0, 1.0, 8.0, 1.0, 0.25, 1.0]);
+    }
+
+    /// KTX2 file with a single level stored right after the 104 byte header.
+    fn ktx2(vk_format: u32, supercompression: u32, offset: u64, length: u64, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; 104];
+        bytes[..12].copy_from_slice(&KTX2_IDENTIFIER);
+        bytes[12..16].copy_from_slice(&vk_format.to_le_bytes());
+        bytes[20..24].copy_from_slice(&2u32.to_le_bytes());
+        bytes[24..28].copy_from_slice(&1u32.to_le_bytes());
+        bytes[44..48].copy_from_slice(&supercompression.to_le_bytes());
+        bytes[80..88].copy_from_slice(&offset.to_le_bytes());
+        bytes[88..96].copy_from_slice(&length.to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn ktx2_reads_the_base_level() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        let decoded = decode_ktx2(&ktx2(43, 0, 104, 8, &data)).unwrap();
+        assert_eq!(decoded.data, data);
+        assert_eq!(decoded.format, Format::R8G8B8A8_SRGB);
+        assert_eq!(decoded.compression, Compression::None);
+        assert_eq!(decoded.dimensions, dimensions_2d(2, 1));
+    }
+
+    #[test]
+    fn ktx2_rejects_bad_headers() {
+        let err = |bytes: &[u8]| decode_ktx2(bytes).err().unwrap();
+        assert_eq!(err(b"not a texture"), "not a KTX2 file");
+        assert_eq!(err(&KTX2_IDENTIFIER), "truncated KTX2 header");
+        assert_eq!(err(&ktx2(12345, 0, 104, 0, &[])), "unsupported VkFormat 12345");
+        assert_eq!(err(&ktx2(43, 1, 104, 0, &[])), "unsupported supercompression scheme 1");
+    }
+
+    #[test]
+    fn ktx2_rejects_level_ranges_outside_the_file() {
+        let err = |bytes: &[u8]| decode_ktx2(bytes).err().unwrap();
+        assert_eq!(err(&ktx2(43, 0, 104, 16, &[0; 8])), "truncated KTX2 level data");
+        assert_eq!(err(&ktx2(43, 0, u64::MAX, 2, &[])), "truncated KTX2 level data");
+        assert_eq!(err(&ktx2(43, 0, 104, u64::MAX, &[])), "truncated KTX2 level data");
+    }
+}
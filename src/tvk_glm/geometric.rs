@@ -1,7 +1,8 @@
-#![allow(dead_code)]
-
 use std::f32::consts::PI;
 
+use super::matrix::Mat4;
+use super::vector::Vec4;
+
 pub fn dot_vec3(a: [f32; 3], b: [f32; 3]) -> f32 {
     a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
 }
@@ -139,6 +140,7 @@ pub fn look_at_rh(eye: [f32; 3], center: [f32; 3], up: [f32; 3]) -> [[f32; 4]; 4
     result
 }
 
+#[allow(dead_code)]
 pub fn perspective_rh_no(fovy: f32, aspect: f32, z_near: f32, z_far: f32) -> [[f32; 4]; 4] {
     let tan_half_fovy = (fovy / 2.0).tan();
     let mut result = zero_mat4();
@@ -151,6 +153,7 @@ pub fn perspective_rh_no(fovy: f32, aspect: f32, z_near: f32, z_far: f32) -> [[f
     result
 }
 
+#[allow(dead_code)]
 pub fn radians(degrees: f32) -> f32 {
     degrees * (PI / 180.0)
 }
@@ -166,3 +169,87 @@ pub fn mult_mat4(a: [[f32; 4]; 4], b: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
     }
     result
 }
+
+/// Maps window coordinates back to object space like glm's `unProjectZO`,
+/// for projections with a 0..1 depth range. `win[2]` is the depth and
+/// `viewport` is (x, y, width, height). Unlike glm, y isn't flipped: our
+/// projections already flip it, so `win` is measured from the top left.
+pub fn unproject_zo(
+    win: [f32; 3],
+    model_view: [[f32; 4]; 4],
+    proj: [[f32; 4]; 4],
+    viewport: [f32; 4],
+) -> Option<[f32; 3]> {
+    let inverse = (Mat4::from(proj) * Mat4::from(model_view)).inverse()?;
+    let ndc = Vec4::new(
+        (win[0] - viewport[0]) / viewport[2] * 2.0 - 1.0,
+        (win[1] - viewport[1]) / viewport[3] * 2.0 - 1.0,
+        win[2],
+        1.0,
+    );
+    let object = inverse * ndc;
+    Some((object.truncate() / object.w).to_array())
+}
+
+pub fn transform_point(m: [[f32; 4]; 4], p: [f32; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (r, value) in result.iter_mut().enumerate() {
+        *value = m[0][r] * p[0] + m[1][r] * p[1] + m[2][r] * p[2] + m[3][r];
+    }
+    result
+}
+
+/// Cofactor matrix of the upper 3x3 block, i.e. the inverse transpose scaled
+/// by the determinant. The sign is kept so mirroring transforms don't flip normals.
+pub fn normal_mat3(m: [[f32; 4]; 4]) -> [[f32; 3]; 3] {
+    let a = |c: usize, r: usize| m[c % 3][r % 3];
+    let mut cofactor = [0, 1, 2].map(|c| {
+        [0, 1, 2].map(|r| a(c + 1, r + 1) * a(c + 2, r + 2) - a(c + 1, r + 2) * a(c + 2, r + 1))
+    });
+    let det = m[0][0] * cofactor[0][0] + m[0][1] * cofactor[0][1] + m[0][2] * cofactor[0][2];
+    if det < 0.0 {
+        for column in cofactor.iter_mut() {
+            for value in column.iter_mut() {
+                *value = -*value;
+            }
+        }
+    }
+    cofactor
+}
+
+pub fn transform_normal(m: [[f32; 3]; 3], n: [f32; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (r, value) in result.iter_mut().enumerate() {
+        *value = m[0][r] * n[0] + m[1][r] * n[1] + m[2][r] * n[2];
+    }
+    if result == [0.0, 0.0, 0.0] {
+        return result;
+    }
+    normalize_vec3(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unproject_inverts_the_projection() {
+        let view = look_at_rh([2.0, 3.0, 4.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+        let proj = Mat4::perspective_rh_zo(radians(60.0), 1.5, 0.1, 100.0).to_cols_array_2d();
+        let viewport = [0.0, 0.0, 600.0, 400.0];
+        let point = Mat4::from(proj) * Mat4::from(view) * Vec4::new(0.3, -0.2, 0.5, 1.0);
+        let ndc = point.truncate() / point.w;
+        let win = [
+            (ndc.x + 1.0) / 2.0 * viewport[2],
+            (ndc.y + 1.0) / 2.0 * viewport[3],
+            ndc.z,
+        ];
+        let unprojected = unproject_zo(win, view, proj, viewport).unwrap();
+        for (a, b) in unprojected.iter().zip([0.3, -0.2, 0.5]) {
+            assert!((a - b).abs() < 1e-3, "{:?}", unprojected);
+        }
+        assert_eq!(unproject_zo(win, view, Mat4::ZERO.to_cols_array_2d(), viewport), None);
+        let center = unproject_zo([300.0, 200.0, 0.0], identity_mat4(), identity_mat4(), viewport);
+        assert_eq!(center, Some([0.0, 0.0, 0.0]));
+    }
+}
@@ -0,0 +1,125 @@
+use super::matrix::Mat4;
+use super::vector::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction }
+    }
+
+    /// Ray from `from` going through `to`, with `to` at t = 1.
+    pub fn between(from: Vec3, to: Vec3) -> Self {
+        Self::new(from, to - from)
+    }
+
+    /// The direction isn't normalized, so `t` keeps meaning the same point
+    /// before and after the transform.
+    pub fn transform(&self, m: &Mat4) -> Self {
+        Self::new(m.transform_point3(self.origin), m.transform_vector3(self.direction))
+    }
+}
+
+/// Möller–Trumbore intersection. Both faces count as hits, returns the ray
+/// parameter of the hit point.
+pub fn ray_triangle(ray: &Ray, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let e1 = b - a;
+    let e2 = c - a;
+    let p = ray.direction.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = ray.direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(q) * inv_det;
+    (t >= 0.0).then_some(t)
+}
+
+/// Slab test against an axis aligned box. Returns where the ray enters the
+/// box, or 0 when it starts inside.
+pub fn ray_aabb(ray: &Ray, min: Vec3, max: Vec3) -> Option<f32> {
+    let mut t_min = 0.0f32;
+    let mut t_max = f32::INFINITY;
+    for axis in 0..3 {
+        let inv = 1.0 / ray.direction[axis];
+        let mut t0 = (min[axis] - ray.origin[axis]) * inv;
+        let mut t1 = (max[axis] - ray.origin[axis]) * inv;
+        if inv < 0.0 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        // NaN appears when the ray is parallel to and on a slab border.
+        t_min = if t0.is_nan() { t_min } else { t_min.max(t0) };
+        t_max = if t1.is_nan() { t_max } else { t_max.min(t1) };
+        if t_max < t_min {
+            return None;
+        }
+    }
+    Some(t_min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_triangle_from_both_sides() {
+        let (a, b, c) = (Vec3::ZERO, Vec3::X, Vec3::Y);
+        let front = Ray::new(Vec3::new(0.25, 0.25, 2.0), -Vec3::Z);
+        let back = Ray::new(Vec3::new(0.25, 0.25, -1.0), Vec3::Z);
+        assert_eq!(ray_triangle(&front, a, b, c), Some(2.0));
+        assert_eq!(ray_triangle(&back, a, b, c), Some(1.0));
+    }
+
+    #[test]
+    fn misses_triangle() {
+        let (a, b, c) = (Vec3::ZERO, Vec3::X, Vec3::Y);
+        let outside = Ray::new(Vec3::new(0.75, 0.75, 1.0), -Vec3::Z);
+        let behind = Ray::new(Vec3::new(0.25, 0.25, 1.0), Vec3::Z);
+        let parallel = Ray::new(Vec3::new(0.25, 0.25, 1.0), Vec3::X);
+        assert_eq!(ray_triangle(&outside, a, b, c), None);
+        assert_eq!(ray_triangle(&behind, a, b, c), None);
+        assert_eq!(ray_triangle(&parallel, a, b, c), None);
+    }
+
+    #[test]
+    fn enters_aabb() {
+        let (min, max) = (Vec3::splat(-1.0), Vec3::splat(1.0));
+        let ray = Ray::new(Vec3::new(0.5, 0.0, 5.0), -Vec3::Z);
+        assert_eq!(ray_aabb(&ray, min, max), Some(4.0));
+        let inside = Ray::new(Vec3::ZERO, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(ray_aabb(&inside, min, max), Some(0.0));
+        let edge = Ray::new(Vec3::new(1.0, 0.0, 5.0), -Vec3::Z);
+        assert_eq!(ray_aabb(&edge, min, max), Some(4.0));
+    }
+
+    #[test]
+    fn misses_aabb() {
+        let (min, max) = (Vec3::splat(-1.0), Vec3::splat(1.0));
+        let beside = Ray::new(Vec3::new(2.0, 0.0, 5.0), -Vec3::Z);
+        let away = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::Z);
+        assert_eq!(ray_aabb(&beside, min, max), None);
+        assert_eq!(ray_aabb(&away, min, max), None);
+    }
+
+    #[test]
+    fn transformed_rays_keep_their_parameter() {
+        let m = Mat4::translation(Vec3::new(1.0, 2.0, 3.0)) * Mat4::scale(Vec3::splat(2.0));
+        let ray = Ray::between(Vec3::ZERO, Vec3::new(1.0, 1.0, 1.0));
+        let moved = ray.transform(&m);
+        assert_eq!(moved.origin + moved.direction, m.transform_point3(ray.origin + ray.direction));
+    }
+}
@@ -0,0 +1,495 @@
+use std::ops::{Index, IndexMut, Mul};
+
+use super::quaternion::Quat;
+use super::vector::{Vec3, Vec4};
+
+/// Column-major 3x3 matrix, `cols[c][r]` like GLSL and glm.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat3 {
+    pub cols: [Vec3; 3],
+}
+
+/// Column-major 4x4 matrix, `cols[c][r]` like GLSL and glm. Unlike
+/// `mult_mat4`, `a * b` is the usual product: `b` is applied first.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub cols: [Vec4; 4],
+}
+
+impl Default for Mat3 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mat3 {
+    pub const ZERO: Self = Self {
+        cols: [Vec3::ZERO; 3],
+    };
+    pub const IDENTITY: Self = Self {
+        cols: [Vec3::X, Vec3::Y, Vec3::Z],
+    };
+
+    pub const fn from_cols(x: Vec3, y: Vec3, z: Vec3) -> Self {
+        Self { cols: [x, y, z] }
+    }
+
+    /// Upper left 3x3 block, i.e. the linear part of an affine transform.
+    pub fn from_mat4(m: Mat4) -> Self {
+        Self::from_cols(m.cols[0].truncate(), m.cols[1].truncate(), m.cols[2].truncate())
+    }
+
+    pub fn from_quat(q: Quat) -> Self {
+        let (xx, yy, zz) = (q.x * q.x, q.y * q.y, q.z * q.z);
+        let (xy, xz, yz) = (q.x * q.y, q.x * q.z, q.y * q.z);
+        let (wx, wy, wz) = (q.w * q.x, q.w * q.y, q.w * q.z);
+        Self::from_cols(
+            Vec3::new(1.0 - 2.0 * (yy + zz), 2.0 * (xy + wz), 2.0 * (xz - wy)),
+            Vec3::new(2.0 * (xy - wz), 1.0 - 2.0 * (xx + zz), 2.0 * (yz + wx)),
+            Vec3::new(2.0 * (xz + wy), 2.0 * (yz - wx), 1.0 - 2.0 * (xx + yy)),
+        )
+    }
+
+    pub fn row(&self, r: usize) -> Vec3 {
+        Vec3::new(self.cols[0][r], self.cols[1][r], self.cols[2][r])
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self::from_cols(self.row(0), self.row(1), self.row(2))
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.cols[0].dot(self.cols[1].cross(self.cols[2]))
+    }
+
+    /// `None` when the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det == 0.0 {
+            return None;
+        }
+        let [a, b, c] = self.cols;
+        // The rows of the inverse are the cross products of the columns.
+        Some(Self::from_cols(b.cross(c), c.cross(a), a.cross(b)).transpose() * (1.0 / det))
+    }
+}
+
+impl Mat4 {
+    pub const ZERO: Self = Self {
+        cols: [Vec4::ZERO; 4],
+    };
+    pub const IDENTITY: Self = Self {
+        cols: [
+            Vec4::new(1.0, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, 1.0, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, 1.0, 0.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        ],
+    };
+
+    pub const fn from_cols(x: Vec4, y: Vec4, z: Vec4, w: Vec4) -> Self {
+        Self { cols: [x, y, z, w] }
+    }
+
+    pub fn from_mat3(m: Mat3) -> Self {
+        Self::from_cols(
+            m.cols[0].extend(0.0),
+            m.cols[1].extend(0.0),
+            m.cols[2].extend(0.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    pub fn from_quat(q: Quat) -> Self {
+        Self::from_mat3(Mat3::from_quat(q))
+    }
+
+    pub fn translation(v: Vec3) -> Self {
+        let mut result = Self::IDENTITY;
+        result.cols[3] = v.extend(1.0);
+        result
+    }
+
+    pub fn scale(v: Vec3) -> Self {
+        let mut result = Self::IDENTITY;
+        result.cols[0].x = v.x;
+        result.cols[1].y = v.y;
+        result.cols[2].z = v.z;
+        result
+    }
+
+    /// Rotation of `angle` radians around `axis`, which doesn't need to be
+    /// normalized.
+    pub fn rotation(angle: f32, axis: Vec3) -> Self {
+        Self::from_quat(Quat::from_axis_angle(axis, angle))
+    }
+
+    pub fn look_at_rh(eye: Vec3, center: Vec3, up: Vec3) -> Self {
+        let f = (center - eye).normalize();
+        let s = f.cross(up).normalize();
+        let u = s.cross(f);
+        Self::from_cols(
+            Vec4::new(s.x, u.x, -f.x, 0.0),
+            Vec4::new(s.y, u.y, -f.y, 0.0),
+            Vec4::new(s.z, u.z, -f.z, 0.0),
+            Vec4::new(-s.dot(eye), -u.dot(eye), f.dot(eye), 1.0),
+        )
+    }
+
+    /// Perspective projection to OpenGL's -1..1 depth range.
+    pub fn perspective_rh_no(fovy: f32, aspect: f32, z_near: f32, z_far: f32) -> Self {
+        let mut result = Self::perspective_rh_zo(fovy, aspect, z_near, z_far);
+        result.cols[2].z = -(z_far + z_near) / (z_far - z_near);
+        result.cols[3].z = -(2.0 * z_far * z_near) / (z_far - z_near);
+        result
+    }
+
+    /// Perspective projection to Vulkan's 0..1 depth range.
+    pub fn perspective_rh_zo(fovy: f32, aspect: f32, z_near: f32, z_far: f32) -> Self {
+        let tan_half_fovy = (fovy / 2.0).tan();
+        let mut result = Self::ZERO;
+        result.cols[0].x = 1.0 / (aspect * tan_half_fovy);
+        result.cols[1].y = 1.0 / tan_half_fovy;
+        result.cols[2].z = z_far / (z_near - z_far);
+        result.cols[2].w = -1.0;
+        result.cols[3].z = -(z_far * z_near) / (z_far - z_near);
+        result
+    }
+
+    /// Perspective projection with the far plane at infinity, -1..1 depth.
+    pub fn perspective_infinite_rh_no(fovy: f32, aspect: f32, z_near: f32) -> Self {
+        let mut result = Self::perspective_infinite_rh_zo(fovy, aspect, z_near);
+        result.cols[3].z = -2.0 * z_near;
+        result
+    }
+
+    /// Perspective projection with the far plane at infinity, 0..1 depth.
+    pub fn perspective_infinite_rh_zo(fovy: f32, aspect: f32, z_near: f32) -> Self {
+        let tan_half_fovy = (fovy / 2.0).tan();
+        let mut result = Self::ZERO;
+        result.cols[0].x = 1.0 / (aspect * tan_half_fovy);
+        result.cols[1].y = 1.0 / tan_half_fovy;
+        result.cols[2].z = -1.0;
+        result.cols[2].w = -1.0;
+        result.cols[3].z = -z_near;
+        result
+    }
+
+    /// Orthographic projection to OpenGL's -1..1 depth range.
+    pub fn orthographic_rh_no(
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        z_near: f32,
+        z_far: f32,
+    ) -> Self {
+        let mut result = Self::orthographic_rh_zo(left, right, bottom, top, z_near, z_far);
+        result.cols[2].z = -2.0 / (z_far - z_near);
+        result.cols[3].z = -(z_far + z_near) / (z_far - z_near);
+        result
+    }
+
+    /// Orthographic projection to Vulkan's 0..1 depth range.
+    pub fn orthographic_rh_zo(
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        z_near: f32,
+        z_far: f32,
+    ) -> Self {
+        let mut result = Self::IDENTITY;
+        result.cols[0].x = 2.0 / (right - left);
+        result.cols[1].y = 2.0 / (top - bottom);
+        result.cols[2].z = -1.0 / (z_far - z_near);
+        result.cols[3].x = -(right + left) / (right - left);
+        result.cols[3].y = -(top + bottom) / (top - bottom);
+        result.cols[3].z = -z_near / (z_far - z_near);
+        result
+    }
+
+    pub fn row(&self, r: usize) -> Vec4 {
+        Vec4::new(self.cols[0][r], self.cols[1][r], self.cols[2][r], self.cols[3][r])
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self::from_cols(self.row(0), self.row(1), self.row(2), self.row(3))
+    }
+
+    /// Determinant of the 3x3 block left after removing column `c` and row `r`.
+    fn minor(&self, c: usize, r: usize) -> f32 {
+        let mut block = Mat3::ZERO;
+        for (bc, mc) in (0..4).filter(|&i| i != c).enumerate() {
+            for (br, mr) in (0..4).filter(|&i| i != r).enumerate() {
+                block.cols[bc][br] = self.cols[mc][mr];
+            }
+        }
+        block.determinant()
+    }
+
+    fn cofactor(&self, c: usize, r: usize) -> f32 {
+        (-1.0f32).powi((c + r) as i32) * self.minor(c, r)
+    }
+
+    pub fn determinant(&self) -> f32 {
+        (0..4).map(|c| self.cols[c].x * self.cofactor(c, 0)).sum()
+    }
+
+    /// `None` when the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det == 0.0 {
+            return None;
+        }
+        let mut result = Self::ZERO;
+        for c in 0..4 {
+            for r in 0..4 {
+                // The adjugate is the transposed cofactor matrix.
+                result.cols[c][r] = self.cofactor(r, c) / det;
+            }
+        }
+        Some(result)
+    }
+
+    /// Inverse transpose of the upper 3x3 block, which keeps normals
+    /// perpendicular to surfaces under non-uniform scaling.
+    pub fn normal_matrix(&self) -> Mat3 {
+        Mat3::from_mat4(*self)
+            .inverse()
+            .map(|m| m.transpose())
+            .unwrap_or(Mat3::IDENTITY)
+    }
+
+    pub fn transform_point3(&self, p: Vec3) -> Vec3 {
+        (*self * p.extend(1.0)).truncate()
+    }
+
+    pub fn transform_vector3(&self, v: Vec3) -> Vec3 {
+        (*self * v.extend(0.0)).truncate()
+    }
+
+    /// Applies the matrix and divides by w, as the rasterizer does.
+    pub fn project_point3(&self, p: Vec3) -> Vec3 {
+        let clip = *self * p.extend(1.0);
+        clip.truncate() / clip.w
+    }
+
+    pub fn to_cols_array_2d(self) -> [[f32; 4]; 4] {
+        self.cols.map(Vec4::to_array)
+    }
+}
+
+impl From<[[f32; 4]; 4]> for Mat4 {
+    fn from(cols: [[f32; 4]; 4]) -> Self {
+        Self {
+            cols: cols.map(Vec4::from),
+        }
+    }
+}
+
+impl From<Mat4> for [[f32; 4]; 4] {
+    fn from(m: Mat4) -> Self {
+        m.to_cols_array_2d()
+    }
+}
+
+impl From<[[f32; 3]; 3]> for Mat3 {
+    fn from(cols: [[f32; 3]; 3]) -> Self {
+        Self {
+            cols: cols.map(Vec3::from),
+        }
+    }
+}
+
+impl From<Mat3> for [[f32; 3]; 3] {
+    fn from(m: Mat3) -> Self {
+        m.cols.map(Vec3::to_array)
+    }
+}
+
+macro_rules! impl_matrix_ops {
+    ($mat:ident, $vec:ident) => {
+        impl Index<usize> for $mat {
+            type Output = $vec;
+
+            fn index(&self, c: usize) -> &$vec {
+                &self.cols[c]
+            }
+        }
+
+        impl IndexMut<usize> for $mat {
+            fn index_mut(&mut self, c: usize) -> &mut $vec {
+                &mut self.cols[c]
+            }
+        }
+
+        impl Mul<$vec> for $mat {
+            type Output = $vec;
+
+            fn mul(self, v: $vec) -> $vec {
+                let mut result = $vec::ZERO;
+                for (c, col) in self.cols.iter().enumerate() {
+                    result += *col * v[c];
+                }
+                result
+            }
+        }
+
+        impl Mul for $mat {
+            type Output = Self;
+
+            fn mul(self, rhs: Self) -> Self {
+                Self {
+                    cols: rhs.cols.map(|col| self * col),
+                }
+            }
+        }
+
+        impl Mul<f32> for $mat {
+            type Output = Self;
+
+            fn mul(self, rhs: f32) -> Self {
+                Self {
+                    cols: self.cols.map(|col| col * rhs),
+                }
+            }
+        }
+    };
+}
+
+impl_matrix_ops!(Mat3, Vec3);
+impl_matrix_ops!(Mat4, Vec4);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tvk_glm::{identity_mat4, mult_mat4, radians, rotate_mat4};
+
+    fn assert_mat4_eq(a: Mat4, b: Mat4) {
+        for c in 0..4 {
+            for r in 0..4 {
+                assert!(
+                    (a[c][r] - b[c][r]).abs() < 1e-5,
+                    "[{}][{}]: {} != {}\n{:?}\n{:?}",
+                    c,
+                    r,
+                    a[c][r],
+                    b[c][r],
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    fn sample() -> Mat4 {
+        Mat4::translation(Vec3::new(1.0, -2.0, 3.0))
+            * Mat4::rotation(radians(30.0), Vec3::new(1.0, 1.0, 0.0))
+            * Mat4::scale(Vec3::new(2.0, 0.5, 3.0))
+    }
+
+    #[test]
+    fn perspective_matches_glm() {
+        // glm::perspective(glm::radians(45.0f), 4.0f / 3.0f, 0.1f, 100.0f)
+        let m = Mat4::perspective_rh_no(radians(45.0), 4.0 / 3.0, 0.1, 100.0);
+        let mut expected = Mat4::ZERO;
+        expected[0][0] = 1.810_660_2;
+        expected[1][1] = 2.414_213_7;
+        expected[2][2] = -1.002_002;
+        expected[2][3] = -1.0;
+        expected[3][2] = -0.200_200_2;
+        assert_mat4_eq(m, expected);
+
+        // glm::perspectiveRH_ZO with the same arguments.
+        let m = Mat4::perspective_rh_zo(radians(45.0), 4.0 / 3.0, 0.1, 100.0);
+        expected[2][2] = -1.001_001;
+        expected[3][2] = -0.100_100_1;
+        assert_mat4_eq(m, expected);
+    }
+
+    #[test]
+    fn infinite_perspective_maps_far_depth_to_the_limit() {
+        let no = Mat4::perspective_infinite_rh_no(radians(60.0), 1.0, 0.1);
+        let zo = Mat4::perspective_infinite_rh_zo(radians(60.0), 1.0, 0.1);
+        let near = Vec3::new(0.0, 0.0, -0.1);
+        let far = Vec3::new(0.0, 0.0, -1.0e6);
+        assert!((no.project_point3(near).z + 1.0).abs() < 1e-5);
+        assert!((zo.project_point3(near).z).abs() < 1e-5);
+        assert!((no.project_point3(far).z - 1.0).abs() < 1e-5);
+        assert!((zo.project_point3(far).z - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn orthographic_matches_glm() {
+        // glm::ortho(-2.0f, 2.0f, -1.0f, 1.0f, 0.1f, 100.0f)
+        let m = Mat4::orthographic_rh_no(-2.0, 2.0, -1.0, 1.0, 0.1, 100.0);
+        let mut expected = Mat4::IDENTITY;
+        expected[0][0] = 0.5;
+        expected[2][2] = -0.020_020_02;
+        expected[3][2] = -1.002_002;
+        assert_mat4_eq(m, expected);
+
+        let m = Mat4::orthographic_rh_zo(-2.0, 2.0, -1.0, 1.0, 0.1, 100.0);
+        expected[2][2] = -0.010_010_01;
+        expected[3][2] = -0.001_001;
+        assert_mat4_eq(m, expected);
+    }
+
+    #[test]
+    fn look_at_moves_the_eye_to_the_origin() {
+        let m = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, Vec3::Y);
+        assert_mat4_eq(m, Mat4::translation(Vec3::new(0.0, 0.0, -5.0)));
+        let m = Mat4::look_at_rh(Vec3::new(3.0, 4.0, 5.0), Vec3::new(1.0, 0.0, -1.0), Vec3::Y);
+        let eye = m.transform_point3(Vec3::new(3.0, 4.0, 5.0));
+        assert!(eye.length() < 1e-5);
+    }
+
+    #[test]
+    fn matches_the_array_functions() {
+        let axis = Vec3::new(0.2, 1.0, -0.5);
+        let rotation = rotate_mat4(identity_mat4(), 1.3, axis.to_array());
+        assert_mat4_eq(Mat4::rotation(1.3, axis), Mat4::from(rotation));
+
+        // mult_mat4(a, b) is b * a.
+        let a = sample();
+        let b = Mat4::from(rotation);
+        assert_mat4_eq(Mat4::from(mult_mat4(a.into(), b.into())), b * a);
+    }
+
+    #[test]
+    fn transpose_and_determinant() {
+        let m = sample();
+        assert_eq!(m.transpose().transpose(), m);
+        assert_eq!(m.transpose()[1][3], m[3][1]);
+        // det(T * R * S) = det(S) = 2 * 0.5 * 3
+        assert!((m.determinant() - 3.0).abs() < 1e-5);
+        assert!((Mat3::from_mat4(m).determinant() - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let m = sample();
+        assert_mat4_eq(m.inverse().unwrap() * m, Mat4::IDENTITY);
+        assert_mat4_eq(m * m.inverse().unwrap(), Mat4::IDENTITY);
+        let m3 = Mat3::from_mat4(m);
+        let product = Mat4::from_mat3(m3.inverse().unwrap() * m3);
+        assert_mat4_eq(product, Mat4::IDENTITY);
+        assert_eq!(Mat4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse(), None);
+    }
+
+    #[test]
+    fn normal_matrix_inverts_scale() {
+        let m = Mat4::scale(Vec3::new(2.0, 4.0, 8.0));
+        let n = Mat4::from_mat3(m.normal_matrix());
+        assert_mat4_eq(n, Mat4::scale(Vec3::new(0.5, 0.25, 0.125)));
+    }
+}
@@ -1,3 +1,7 @@
 pub mod geometric;
+pub mod intersect;
+pub mod matrix;
+pub mod quaternion;
+pub mod vector;
 
 pub use geometric::*;
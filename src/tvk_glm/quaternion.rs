@@ -0,0 +1,241 @@
+use std::ops::{Mul, Neg};
+
+use super::matrix::{Mat3, Mat4};
+use super::vector::{Vec3, Vec4};
+
+/// Rotation quaternion, `w` being the real part. Products compose like
+/// matrices: `a * b` applies `b` first.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Quat {
+    pub const IDENTITY: Self = Self::new(0.0, 0.0, 0.0, 1.0);
+
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+
+    /// Rotation of `angle` radians around `axis`, which doesn't need to be
+    /// normalized.
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let (s, c) = (angle * 0.5).sin_cos();
+        let v = axis.normalize() * s;
+        Self::new(v.x, v.y, v.z, c)
+    }
+
+    /// Same convention as glm's `quat(vec3)`: `angles` holds pitch (X), yaw (Y)
+    /// and roll (Z) in radians.
+    pub fn from_euler(angles: Vec3) -> Self {
+        let (sx, cx) = (angles.x * 0.5).sin_cos();
+        let (sy, cy) = (angles.y * 0.5).sin_cos();
+        let (sz, cz) = (angles.z * 0.5).sin_cos();
+        Self::new(
+            sx * cy * cz - cx * sy * sz,
+            cx * sy * cz + sx * cy * sz,
+            cx * cy * sz - sx * sy * cz,
+            cx * cy * cz + sx * sy * sz,
+        )
+    }
+
+    /// Inverse of `from_euler`, matching glm's `eulerAngles`.
+    pub fn to_euler(self) -> Vec3 {
+        let Self { x, y, z, w } = self;
+        let sin_pitch_y = 2.0 * (y * z + w * x);
+        let sin_pitch_x = w * w - x * x - y * y + z * z;
+        // Gimbal lock, glm falls back to atan2(x, w).
+        let pitch = if sin_pitch_y.abs() < f32::EPSILON && sin_pitch_x.abs() < f32::EPSILON {
+            2.0 * x.atan2(w)
+        } else {
+            sin_pitch_y.atan2(sin_pitch_x)
+        };
+        let yaw = (-2.0 * (x * z - w * y)).clamp(-1.0, 1.0).asin();
+        let roll = (2.0 * (x * y + w * z)).atan2(w * w + x * x - y * y - z * z);
+        Vec3::new(pitch, yaw, roll)
+    }
+
+    /// Axis and angle in radians of the rotation, the angle in 0..2π. Without
+    /// any rotation the axis is arbitrary, Y is returned.
+    pub fn to_axis_angle(self) -> (Vec3, f32) {
+        let q = self.normalize();
+        let angle = 2.0 * q.w.clamp(-1.0, 1.0).acos();
+        let s = (1.0 - q.w * q.w).max(0.0).sqrt();
+        if s < 1e-6 {
+            return (Vec3::Y, 0.0);
+        }
+        (Vec3::new(q.x, q.y, q.z) / s, angle)
+    }
+
+    fn as_vec4(self) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, self.w)
+    }
+
+    fn from_vec4(v: Vec4) -> Self {
+        Self::new(v.x, v.y, v.z, v.w)
+    }
+
+    pub fn dot(self, rhs: Self) -> f32 {
+        self.as_vec4().dot(rhs.as_vec4())
+    }
+
+    pub fn length(self) -> f32 {
+        self.as_vec4().length()
+    }
+
+    /// A zero quaternion has no rotation to keep and is returned as is.
+    pub fn normalize(self) -> Self {
+        Self::from_vec4(self.as_vec4().normalize())
+    }
+
+    pub fn conjugate(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn inverse(self) -> Self {
+        let c = self.conjugate().as_vec4() / self.dot(self);
+        Self::from_vec4(c)
+    }
+
+    /// Spherical interpolation along the shortest arc, falling back to a
+    /// linear one when both rotations are almost the same.
+    pub fn slerp(self, rhs: Self, t: f32) -> Self {
+        let mut end = rhs;
+        let mut cos_theta = self.dot(rhs);
+        if cos_theta < 0.0 {
+            end = -rhs;
+            cos_theta = -cos_theta;
+        }
+        let (a, b) = (self.as_vec4(), end.as_vec4());
+        if cos_theta > 1.0 - f32::EPSILON {
+            return Self::from_vec4(a.lerp(b, t));
+        }
+        let angle = cos_theta.acos();
+        let v = (a * ((1.0 - t) * angle).sin() + b * (t * angle).sin()) / angle.sin();
+        Self::from_vec4(v)
+    }
+
+    pub fn to_mat3(self) -> Mat3 {
+        Mat3::from_quat(self)
+    }
+
+    pub fn to_mat4(self) -> Mat4 {
+        Mat4::from_quat(self)
+    }
+}
+
+impl Neg for Quat {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+impl Mul for Quat {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let (p, q) = (self, rhs);
+        Self::new(
+            p.w * q.x + p.x * q.w + p.y * q.z - p.z * q.y,
+            p.w * q.y + p.y * q.w + p.z * q.x - p.x * q.z,
+            p.w * q.z + p.z * q.w + p.x * q.y - p.y * q.x,
+            p.w * q.w - p.x * q.x - p.y * q.y - p.z * q.z,
+        )
+    }
+}
+
+impl Mul<Vec3> for Quat {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        let u = Vec3::new(self.x, self.y, self.z);
+        let uv = u.cross(v);
+        let uuv = u.cross(uv);
+        v + (uv * self.w + uuv) * 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tvk_glm::radians;
+
+    fn assert_vec3_eq(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_quat_eq(a: Quat, b: Quat) {
+        // q and -q are the same rotation.
+        assert!(a.dot(b).abs() > 1.0 - 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn rotates_vectors() {
+        let q = Quat::from_axis_angle(Vec3::Z, radians(90.0));
+        assert_vec3_eq(q * Vec3::X, Vec3::Y);
+        assert_vec3_eq(q.inverse() * Vec3::Y, Vec3::X);
+        let v = Vec3::new(0.3, -1.2, 2.0);
+        assert_vec3_eq(q * v, q.to_mat3() * v);
+    }
+
+    #[test]
+    fn products_apply_the_right_operand_first() {
+        let a = Quat::from_axis_angle(Vec3::X, radians(90.0));
+        let b = Quat::from_axis_angle(Vec3::Z, radians(90.0));
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        assert_vec3_eq((a * b) * v, a * (b * v));
+        assert_vec3_eq((a * b) * v, (a.to_mat4() * b.to_mat4()).transform_point3(v));
+    }
+
+    #[test]
+    fn euler_matches_glm() {
+        // glm::quat(glm::vec3(0.1f, 0.2f, 0.3f))
+        let q = Quat::from_euler(Vec3::new(0.1, 0.2, 0.3));
+        assert_quat_eq(q, Quat::new(0.034_270_797, 0.106_021_06, 0.143_572_23, 0.983_347_7));
+        assert_vec3_eq(q.to_euler(), Vec3::new(0.1, 0.2, 0.3));
+        // Same as rotating around X, then Y, then Z.
+        let composed = Quat::from_axis_angle(Vec3::Z, 0.3)
+            * Quat::from_axis_angle(Vec3::Y, 0.2)
+            * Quat::from_axis_angle(Vec3::X, 0.1);
+        assert_quat_eq(q, composed);
+    }
+
+    #[test]
+    fn axis_angle_round_trip() {
+        let axis = Vec3::new(1.0, 2.0, -2.0).normalize();
+        let (back, angle) = Quat::from_axis_angle(axis, 1.25).to_axis_angle();
+        assert_vec3_eq(back, axis);
+        assert!((angle - 1.25).abs() < 1e-5);
+        assert_eq!(Quat::IDENTITY.to_axis_angle(), (Vec3::Y, 0.0));
+    }
+
+    #[test]
+    fn slerp_follows_the_shortest_arc() {
+        let a = Quat::IDENTITY;
+        let b = Quat::from_axis_angle(Vec3::Z, radians(90.0));
+        assert_quat_eq(a.slerp(b, 0.5), Quat::from_axis_angle(Vec3::Z, radians(45.0)));
+        assert_quat_eq(a.slerp(-b, 0.5), Quat::from_axis_angle(Vec3::Z, radians(45.0)));
+        assert_quat_eq(a.slerp(b, 0.0), a);
+        assert_quat_eq(a.slerp(b, 1.0), b);
+        assert!((a.slerp(b, 0.3).length() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn normalizing_zero_keeps_it() {
+        let zero = Quat::new(0.0, 0.0, 0.0, 0.0);
+        assert_eq!(zero.normalize(), zero);
+        assert_quat_eq(Quat::new(0.0, 0.0, 0.0, 3.0).normalize(), Quat::IDENTITY);
+    }
+}
@@ -0,0 +1,264 @@
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// Implements the component-wise operators, the conversions from and to plain
+/// arrays and the functions every vector size shares.
+macro_rules! impl_vector {
+    ($name:ident, $n:literal, $($field:ident),+) => {
+        impl $name {
+            pub const ZERO: Self = Self { $($field: 0.0),+ };
+            pub const ONE: Self = Self { $($field: 1.0),+ };
+
+            pub const fn new($($field: f32),+) -> Self {
+                Self { $($field),+ }
+            }
+
+            pub const fn splat(value: f32) -> Self {
+                Self { $($field: value),+ }
+            }
+
+            pub fn dot(self, rhs: Self) -> f32 {
+                0.0 $(+ self.$field * rhs.$field)+
+            }
+
+            pub fn length_squared(self) -> f32 {
+                self.dot(self)
+            }
+
+            pub fn length(self) -> f32 {
+                self.dot(self).sqrt()
+            }
+
+            pub fn distance(self, rhs: Self) -> f32 {
+                (self - rhs).length()
+            }
+
+            /// Returns the vector unchanged when it is too short to have a
+            /// direction, instead of filling it with NaN.
+            pub fn normalize(self) -> Self {
+                let length = self.length();
+                if length <= f32::EPSILON {
+                    return self;
+                }
+                self / length
+            }
+
+            pub fn lerp(self, rhs: Self, t: f32) -> Self {
+                self + (rhs - self) * t
+            }
+
+            pub fn min(self, rhs: Self) -> Self {
+                Self { $($field: self.$field.min(rhs.$field)),+ }
+            }
+
+            pub fn max(self, rhs: Self) -> Self {
+                Self { $($field: self.$field.max(rhs.$field)),+ }
+            }
+
+            pub fn to_array(self) -> [f32; $n] {
+                [$(self.$field),+]
+            }
+        }
+
+        impl From<[f32; $n]> for $name {
+            fn from(array: [f32; $n]) -> Self {
+                let [$($field),+] = array;
+                Self { $($field),+ }
+            }
+        }
+
+        impl From<$name> for [f32; $n] {
+            fn from(vector: $name) -> Self {
+                vector.to_array()
+            }
+        }
+
+        impl Index<usize> for $name {
+            type Output = f32;
+
+            fn index(&self, index: usize) -> &f32 {
+                [$(&self.$field),+][index]
+            }
+        }
+
+        impl IndexMut<usize> for $name {
+            fn index_mut(&mut self, index: usize) -> &mut f32 {
+                [$(&mut self.$field),+].into_iter().nth(index).expect("vector index out of range")
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self {
+                Self { $($field: self.$field + rhs.$field),+ }
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self {
+                Self { $($field: self.$field - rhs.$field),+ }
+            }
+        }
+
+        impl Mul for $name {
+            type Output = Self;
+
+            fn mul(self, rhs: Self) -> Self {
+                Self { $($field: self.$field * rhs.$field),+ }
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = Self;
+
+            fn mul(self, rhs: f32) -> Self {
+                Self { $($field: self.$field * rhs),+ }
+            }
+        }
+
+        impl Mul<$name> for f32 {
+            type Output = $name;
+
+            fn mul(self, rhs: $name) -> $name {
+                rhs * self
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = Self;
+
+            fn div(self, rhs: f32) -> Self {
+                Self { $($field: self.$field / rhs),+ }
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+
+            fn neg(self) -> Self {
+                Self { $($field: -self.$field),+ }
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl MulAssign<f32> for $name {
+            fn mul_assign(&mut self, rhs: f32) {
+                *self = *self * rhs;
+            }
+        }
+
+        impl DivAssign<f32> for $name {
+            fn div_assign(&mut self, rhs: f32) {
+                *self = *self / rhs;
+            }
+        }
+    };
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl_vector!(Vec2, 2, x, y);
+impl_vector!(Vec3, 3, x, y, z);
+impl_vector!(Vec4, 4, x, y, z, w);
+
+impl Vec3 {
+    pub const X: Self = Self::new(1.0, 0.0, 0.0);
+    pub const Y: Self = Self::new(0.0, 1.0, 0.0);
+    pub const Z: Self = Self::new(0.0, 0.0, 1.0);
+
+    pub fn cross(self, rhs: Self) -> Self {
+        Self::new(
+            self.y * rhs.z - rhs.y * self.z,
+            self.z * rhs.x - rhs.z * self.x,
+            self.x * rhs.y - rhs.x * self.y,
+        )
+    }
+
+    pub fn extend(self, w: f32) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, w)
+    }
+}
+
+impl Vec4 {
+    pub fn truncate(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators_are_component_wise() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let b = Vec3::new(4.0, 5.0, 6.0);
+        assert_eq!(a + b, Vec3::new(5.0, 7.0, 9.0));
+        assert_eq!(b - a, Vec3::splat(3.0));
+        assert_eq!(a * b, Vec3::new(4.0, 10.0, 18.0));
+        assert_eq!(2.0 * a, Vec3::new(2.0, 4.0, 6.0));
+        assert_eq!(-a / 2.0, Vec3::new(-0.5, -1.0, -1.5));
+        assert_eq!(a[2], 3.0);
+    }
+
+    #[test]
+    fn dot_cross_and_length() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let b = Vec3::new(4.0, 5.0, 6.0);
+        assert_eq!(a.dot(b), 32.0);
+        assert_eq!(a.cross(b), Vec3::new(-3.0, 6.0, -3.0));
+        assert_eq!(Vec3::X.cross(Vec3::Y), Vec3::Z);
+        assert_eq!(Vec2::new(3.0, 4.0).length(), 5.0);
+        assert!((Vec4::new(1.0, 1.0, 1.0, 1.0).normalize().length() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn normalizing_zero_keeps_it() {
+        assert_eq!(Vec3::ZERO.normalize(), Vec3::ZERO);
+        let tiny = Vec2::new(1e-30, 0.0);
+        assert_eq!(tiny.normalize(), tiny);
+        assert_eq!(Vec3::new(0.0, 0.0, 2.0).normalize(), Vec3::Z);
+    }
+
+    #[test]
+    fn array_round_trip() {
+        let v = Vec4::from([1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(v.truncate().extend(4.0), v);
+        assert_eq!(<[f32; 4]>::from(v), [1.0, 2.0, 3.0, 4.0]);
+    }
+}
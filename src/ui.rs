@@ -8,6 +8,12 @@ use vulkano_util::context::{VulkanoConfig, VulkanoContext};
 use vulkano_util::window::{VulkanoWindows, WindowDescriptor};
 use winit::event_loop::EventLoop;
 
+use std::{path::Path, sync::Arc};
+
+use crate::assets::export;
+use crate::camera_controls::{self, CameraControls};
+use crate::language::program::Program;
+use crate::language::types::{Camera, InterpretingMode, Model};
 use crate::rendering_pipeline::MSAAPipeline;
 
 pub struct Application {
@@ -15,16 +21,30 @@ pub struct Application {
     pub windows: VulkanoWindows,
     pub pipeline: MSAAPipeline,
     pub changed_input: bool,
+    /// Whether the last change came from Run or Ctrl+Enter rather than from
+    /// typing, only then are the script's exports written.
+    pub run_requested: bool,
+    pub interpreting_mode: InterpretingMode,
+    pub picked: Option<String>,
+    pub camera_controls: CameraControls,
     pub gui: Gui,
 }
 
 impl Application {
     pub fn new(event_loop: &EventLoop<()>) -> Self {
         let mut vk_config = VulkanoConfig::default();
-        vk_config.device_features = Features {
+        let features = Features {
             multi_draw_indirect: true,
+            sampler_anisotropy: true,
             ..Features::empty()
         };
+        // `VulkanoContext` fixes the features before picking the device, so
+        // only devices able to enable all of them are considered.
+        let extensions = vk_config.device_extensions;
+        vk_config.device_filter_fn = Arc::new(move |p| {
+            p.supported_extensions().contains(&extensions) && p.supported_features().contains(&features)
+        });
+        vk_config.device_features = features;
         let context = VulkanoContext::new(vk_config);
         let mut windows = VulkanoWindows::default();
 
@@ -63,16 +83,18 @@ impl Application {
             windows,
             pipeline,
             changed_input: false,
+            run_requested: false,
+            interpreting_mode: InterpretingMode::Continuous,
+            picked: None,
+            camera_controls: CameraControls::default(),
             gui,
         }
     }
 
     pub fn gui_panel(
-        changed: &mut bool,
+        state: &mut PanelState,
         app_info: &mut AppInfo,
         vk_ratio: &mut f32,
-        code: &mut String,
-        console: &mut String,
         gui: &mut Gui,
     ) {
         let ctx = gui.context();
@@ -86,19 +108,37 @@ impl Application {
                 let row_height = ui.fonts().row_height(&font);
                 let editor_height = ui.available_height() / 1.75;
                 let editor_rows = editor_height / row_height;
-                let editor = TextEdit::multiline(code)
+                let editor = TextEdit::multiline(state.code)
                     .font(font)
                     .desired_width(ui.available_width())
                     .desired_rows(editor_rows as usize);
                 ui.set_min_width(app_info.min_width);
+                let run_shortcut = ui
+                    .input_mut()
+                    .consume_key(egui::Modifiers::CTRL, egui::Key::Enter);
+                let mut edited = false;
                 ScrollArea::vertical()
                     .max_height(ui.available_height() / 1.75)
                     .show_rows(ui, row_height, editor_rows as usize - 5, |ui, _| {
                         ui.set_height(ui.available_height());
-                        *changed = ui.add(editor).changed();
+                        edited = ui.add(editor).changed();
+                    });
+                *state.run = match state.mode {
+                    InterpretingMode::Continuous => run_shortcut,
+                    InterpretingMode::Manual => {
+                        let run_clicked = ui.button("Run (Ctrl+Enter)").clicked();
+                        run_clicked || run_shortcut
+                    }
+                };
+                *state.changed = *state.run || (state.mode == InterpretingMode::Continuous && edited);
+                ScrollArea::vertical()
+                    .id_source("console")
+                    .max_height(row_height * 4.0)
+                    .show(ui, |ui| {
+                        ui.label(egui::RichText::new(state.console.as_str()).monospace());
                     });
                 ui.separator();
-                Self::lower_panel(app_info.panel_width * 0.86, ui);
+                Self::lower_panel(app_info.panel_width * 0.86, state, ui);
                 if app_info.panel_width != ui.available_width() + 20.0 {
                     app_info.panel_width = ui.available_width() + 20.0;
                     *vk_ratio = 1.0
@@ -108,7 +148,27 @@ impl Application {
             });
     }
 
-    fn lower_panel(width: f32, ui: &mut Ui) {
+    /// Name shown for the `i`th drawn model. Models drawn without a binding
+    /// are numbered.
+    pub fn model_label(i: usize, model: &Model) -> String {
+        if model.name.is_empty() {
+            format!("model{}", i)
+        } else {
+            model.name.clone()
+        }
+    }
+
+    /// Lists the drawn models, highlighting the picked one, with buttons
+    /// exporting each one next to the working directory. Results are reported
+    /// in the console. Next to it are the viewport camera buttons, the matrix
+    /// of the picked model and the animation clock.
+    fn lower_panel(width: f32, state: &mut PanelState, ui: &mut Ui) {
+        let models = state.models;
+        let picked = &mut *state.picked;
+        let view_camera = &mut *state.view_camera;
+        let program = &mut *state.program;
+        let code = &mut *state.code;
+        let console = &mut *state.console;
         ui.columns(2, |columns| {
             columns[0].push_id(0, |ui|{
                 TableBuilder::new(ui)
@@ -119,26 +179,117 @@ impl Application {
                             ui.heading("Name");
                         });
                         header.col(|ui| {
-                            ui.heading("Type");
+                            ui.heading("Export");
                         });
                     })
                 .body(|mut body| {
-                    body.row(26.0, |mut row| {
-                        row.col(|ui| {
-                            if ui.button("object").clicked() {
-                                println!("object");
-                            }
-                        });
-                        row.col(|ui| {
-                            ui.label("NaN");
+                    for (i, model) in models.iter().enumerate() {
+                        let name = Self::model_label(i, model);
+                        body.row(26.0, |mut row| {
+                            row.col(|ui| {
+                                let selected = picked.as_ref() == Some(&name);
+                                if ui.selectable_label(selected, &name).clicked() {
+                                    *picked = if selected { None } else { Some(name.clone()) };
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.horizontal(|ui| {
+                                    let models = std::slice::from_ref(model);
+                                    let mut result = None;
+                                    let stem = export::file_stem(&name);
+                                    if ui.button("OBJ").clicked() {
+                                        let path = format!("{}.obj", stem);
+                                        result = Some(export::export_obj(models, Path::new(&path)).map(|_| path));
+                                    }
+                                    if ui.button("PLY").clicked() {
+                                        let path = format!("{}.ply", stem);
+                                        result = Some(export::export_ply(models, Path::new(&path)).map(|_| path));
+                                    }
+                                    match result {
+                                        Some(Ok(path)) => console.push_str(&format!("\nexported {}", path)),
+                                        Some(Err(e)) => console.push_str(&format!("\nexport: {}", e)),
+                                        None => (),
+                                    }
+                                });
+                            });
                         });
-                    });
+                    }
                 });
             });
+            let ui = &mut columns[1];
+            ui.heading("Camera");
+            ui.add_enabled_ui(view_camera.is_some(), |ui| {
+                if ui.button("Copy camera to script").clicked() {
+                    if let Some(camera) = view_camera {
+                        if let Err(e) = camera_controls::copy_to_script(code, camera) {
+                            console.push_str(&format!("\n{}", e));
+                        }
+                    }
+                }
+                if ui.button("Reset camera").clicked() {
+                    *view_camera = None;
+                }
+            });
+            ui.separator();
+            ui.heading("Model matrix");
+            let selected = models
+                .iter()
+                .enumerate()
+                .find(|(i, model)| picked.as_ref() == Some(&Self::model_label(*i, model)));
+            match selected {
+                Some((_, model)) => {
+                    let matrix = model.world_mat4();
+                    let rows: Vec<String> = (0..4)
+                        .map(|r| {
+                            format!(
+                                "{:>8.3} {:>8.3} {:>8.3} {:>8.3}",
+                                matrix[0][r], matrix[1][r], matrix[2][r], matrix[3][r]
+                            )
+                        })
+                        .collect();
+                    ui.label(egui::RichText::new(rows.join("\n")).monospace());
+                },
+                None => {
+                    ui.label("Pick a model to see it.");
+                },
+            }
+            ui.separator();
+            ui.heading("Animation");
+            ui.horizontal(|ui| {
+                let label = if program.playing { "Pause" } else { "Play" };
+                if ui.button(label).clicked() {
+                    program.playing = !program.playing;
+                }
+                if ui.button("Restart").clicked() {
+                    program.time = 0.0;
+                    program.frame = 0;
+                }
+                ui.label(format!("frame {}", program.frame));
+            });
+            let end = if program.duration > 0.0 { program.duration } else { 10.0 };
+            let scrub = ui.add(egui::Slider::new(&mut program.time, 0.0..=end).text("time"));
+            // Scrubbing pauses, or the clock would move away from the picked time.
+            if scrub.dragged() {
+                program.playing = false;
+            }
         });
     }
 }
 
+/// What the side panel reads and edits besides its own layout. `changed`
+/// and `run` are set when the script has to be evaluated again.
+pub struct PanelState<'a> {
+    pub code: &'a mut String,
+    pub console: &'a mut String,
+    pub program: &'a mut Program,
+    pub models: &'a [Model],
+    pub picked: &'a mut Option<String>,
+    pub view_camera: &'a mut Option<Camera>,
+    pub mode: InterpretingMode,
+    pub changed: &'a mut bool,
+    pub run: &'a mut bool,
+}
+
 pub struct AppInfo {
     window_size: [f32; 2],
     panel_width: f32,